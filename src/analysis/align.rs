@@ -0,0 +1,257 @@
+
+use failure::{Error, err_msg};
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::mpsc::{channel, Receiver};
+
+use song::decode;
+use configuration::config;
+use super::{mono, energy};
+
+/// Estimated position of a single lyrics line in a song, in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct LineTiming {
+    pub line: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+pub enum AlignMsg {
+    Done(Vec<LineTiming>),
+    Failed(Error)
+}
+
+/// Estimates where each lyrics line is sung. `lines` contains line indexes
+/// (as used by `TimeFrame::line`) and text of non empty lines in order
+pub trait Aligner: Send {
+    fn align(&self, song: &Path, lines: &[(usize, String)]) -> Result<Vec<LineTiming>, Error>;
+}
+
+/// Returns the aligner configured in the `[alignment]` section of settings
+pub fn aligner() -> Box<Aligner> {
+    match config().alignment.command {
+        Some(ref command) => Box::new(CommandAligner::new(command.clone())),
+        None => Box::new(EnergyAligner::new(
+            config().alignment.frame_ms,
+            config().alignment.min_silence_ms,
+            config().alignment.snap_window_ms)),
+    }
+}
+
+pub fn align<P: AsRef<Path>>(song: P, lines: Vec<(usize, String)>) -> Receiver<AlignMsg> {
+    use std::thread;
+
+    let song: PathBuf = song.as_ref().into();
+    let aligner = aligner();
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        match aligner.align(&song, &lines) {
+            Ok(timings) => tx.send(AlignMsg::Done(timings)).expect("Can't send signal"),
+            Err(e) => tx.send(AlignMsg::Failed(e)).expect("Can't send signal")
+        }
+    });
+
+    rx
+}
+
+/// Heuristic aligner. Finds parts of the song with audible signal and
+/// splits them between lines proportionally to the lines length, then moves
+/// every line start to the strongest onset nearby
+pub struct EnergyAligner {
+    frame_ms: f32,
+    min_silence_ms: f32,
+    snap_window_ms: f32,
+}
+
+impl EnergyAligner {
+    pub fn new(frame_ms: f32, min_silence_ms: f32, snap_window_ms: f32) -> Self {
+        EnergyAligner { frame_ms, min_silence_ms, snap_window_ms }
+    }
+
+    #[inline]
+    fn frames(&self, ms: f32) -> usize {
+        (ms / self.frame_ms).round() as usize
+    }
+}
+
+impl Aligner for EnergyAligner {
+    fn align(&self, song: &Path, lines: &[(usize, String)]) -> Result<Vec<LineTiming>, Error> {
+        ensure!(!lines.is_empty(), "There are no lyrics lines to align");
+
+        let song = decode(song)?;
+        let window = ((song.sample_rate as f32 * self.frame_ms / 1000.0) as usize).max(1);
+        let frame_secs = window as f64 / song.sample_rate as f64;
+        let loudness = energy(&mono(song.channels, &song.samples), window);
+        ensure!(!loudness.is_empty(), "Song is empty");
+
+        let active = activity(&loudness, self.frames(self.min_silence_ms));
+        let total_active = active.iter().filter(|&&a| a).count();
+        ensure!(total_active > 0, "No audible signal found in the song");
+
+        let weights = lines.iter().map(|&(_, ref text)| weight(text)).collect::<Vec<_>>();
+        let total_weight = weights.iter().sum::<usize>();
+
+        let mut bounds = Vec::with_capacity(lines.len() + 1);
+        let mut acc = 0;
+        for w in weights {
+            bounds.push(nth_active(&active, total_active * acc / total_weight));
+            acc += w;
+        }
+        bounds.push(active.iter().rposition(|&a| a).map(|x| x + 1).unwrap_or(active.len()));
+
+        let onsets = onset_strength(&loudness);
+        let radius = self.frames(self.snap_window_ms);
+        for i in 0..lines.len() {
+            let low = if i == 0 { 0 } else { bounds[i - 1] + 1 };
+            let high = bounds[i + 1].saturating_sub(1);
+            bounds[i] = snap_to_onset(&onsets, bounds[i], radius, low, high);
+        }
+
+        Ok(lines.iter()
+           .enumerate()
+           .map(|(i, &(line, _))| {
+               let end = trim_silence(&active, bounds[i], bounds[i + 1]);
+               LineTiming {
+                   line,
+                   start: bounds[i] as f64 * frame_secs,
+                   end: end as f64 * frame_secs,
+               }
+           })
+           .collect())
+    }
+}
+
+/// Delegates alignment to an external program (for example a local speech
+/// model). The program is called with the song path and a path to a file with
+/// one lyrics line per row, and must print `start end` (in seconds) per row
+pub struct CommandAligner {
+    command: String,
+}
+
+impl CommandAligner {
+    pub fn new(command: String) -> Self {
+        CommandAligner { command }
+    }
+}
+
+impl Aligner for CommandAligner {
+    fn align(&self, song: &Path, lines: &[(usize, String)]) -> Result<Vec<LineTiming>, Error> {
+        let mut args = self.command.split_whitespace();
+        let program = args.next().ok_or(err_msg("Alignment command is empty"))?;
+
+        let (lyrics, mut file) = lyrics_file()?;
+        let written = lines.iter().map(|&(_, ref text)| writeln!(file, "{}", text)).collect::<Result<(), _>>();
+        drop(file);
+        let output = written.and_then(|_| Command::new(program).args(args).arg(song).arg(&lyrics).output());
+        let _ = fs::remove_file(&lyrics);
+        let output = output?;
+        ensure!(output.status.success(), "Alignment command failed: {}",
+                String::from_utf8_lossy(&output.stderr));
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let timings = stdout.lines()
+            .filter(|row| !row.trim().is_empty())
+            .zip(lines)
+            .map(|(row, &(line, _))| {
+                let mut values = row.split_whitespace().map(|v| v.parse::<f64>());
+                match (values.next(), values.next()) {
+                    (Some(Ok(start)), Some(Ok(end))) => Ok(LineTiming { line, start, end }),
+                    _ => Err(format_err!("Can't parse alignment output: {}", row))
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        ensure!(timings.len() == lines.len(), "Alignment command returned {} rows for {} lines",
+                timings.len(), lines.len());
+
+        Ok(timings)
+    }
+}
+
+/// New file in the temporary directory, alignments running at the same time
+/// in this or another instance get their own
+fn lyrics_file() -> Result<(PathBuf, File), Error> {
+    let mut attempt = 0;
+    loop {
+        let path = env::temp_dir().join(format!("melos_alignment_{}_{}.txt", process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists && attempt < 1000 => attempt += 1,
+            Err(e) => return Err(e.into())
+        }
+    }
+}
+
+/// Approximates how long a line is sung
+fn weight(text: &str) -> usize {
+    text.chars().filter(|c| c.is_alphanumeric()).count().max(1)
+}
+
+/// Marks frames louder than the adaptive threshold. Silence shorter than
+/// `min_gap` frames is treated as a part of the surrounding phrase
+fn activity(loudness: &[f32], min_gap: usize) -> Vec<bool> {
+    let mut sorted = loudness.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let noise = sorted[sorted.len() / 10];
+    let peak = sorted[sorted.len() * 95 / 100];
+    let threshold = noise + (peak - noise) * 0.25;
+
+    let mut active = loudness.iter().map(|&l| l > threshold).collect::<Vec<_>>();
+    let mut last_active = None;
+    for i in 0..active.len() {
+        if active[i] {
+            if let Some(last) = last_active {
+                if i - last - 1 < min_gap {
+                    for a in &mut active[last + 1..i] {
+                        *a = true;
+                    }
+                }
+            }
+            last_active = Some(i);
+        }
+    }
+    active
+}
+
+/// Index of the frame after `count` active frames
+fn nth_active(active: &[bool], count: usize) -> usize {
+    active.iter()
+        .enumerate()
+        .filter(|&(_, &a)| a)
+        .nth(count)
+        .map(|(i, _)| i)
+        .unwrap_or(active.len())
+}
+
+fn onset_strength(loudness: &[f32]) -> Vec<f32> {
+    let mut onsets = vec![0.0; loudness.len()];
+    for i in 1..loudness.len() {
+        onsets[i] = (loudness[i] - loudness[i - 1]).max(0.0);
+    }
+    onsets
+}
+
+fn snap_to_onset(onsets: &[f32], pos: usize, radius: usize, low: usize, high: usize) -> usize {
+    let from = pos.saturating_sub(radius).max(low);
+    let to = (pos + radius).min(high).min(onsets.len().saturating_sub(1));
+    if from > to {
+        return pos;
+    }
+
+    (from..to + 1)
+        .max_by(|&a, &b| onsets[a].partial_cmp(&onsets[b]).unwrap())
+        .unwrap_or(pos)
+}
+
+/// Moves line end back to the last active frame before the next line
+fn trim_silence(active: &[bool], start: usize, next: usize) -> usize {
+    let mut end = next.min(active.len());
+    while end > start + 1 && !active[end - 1] {
+        end -= 1;
+    }
+    end
+}
@@ -0,0 +1,29 @@
+
+mod align;
+mod repeats;
+
+pub use self::align::*;
+pub use self::repeats::*;
+
+use song::Sample;
+
+/// Downmixes samples interleaved by channels into mono samples in `[-1.0, 1.0]` range
+pub fn mono(channels: u16, samples: &[Sample]) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    samples.chunks(channels)
+        .map(|frame| {
+            let sum = frame.iter().map(|&s| s as f32).sum::<f32>();
+            sum / channels as f32 / i16::max_value() as f32
+        })
+        .collect()
+}
+
+/// Loudness (in dB) of consecutive non overlapping windows of `window` samples
+pub fn energy(samples: &[f32], window: usize) -> Vec<f32> {
+    samples.chunks(window.max(1))
+        .map(|chunk| {
+            let power = chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32;
+            10.0 * (power + 1e-10).log10()
+        })
+        .collect()
+}
@@ -0,0 +1,352 @@
+
+use failure::Error;
+
+use std::f32::consts::PI;
+use std::sync::mpsc::{channel, Receiver};
+
+use song::Track;
+use configuration::config;
+use super::mono;
+
+/// Samples analysed for every chroma vector
+const FFT_SIZE: usize = 4096;
+/// Frequencies outside of this range are too noisy to tell the pitch
+const MIN_FREQ: f32 = 80.0;
+const MAX_FREQ: f32 = 5000.0;
+
+type Chroma = [f32; 12];
+
+/// Part of the song which sounds like an earlier one, times are in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct Repetition {
+    pub source: (f64, f64),
+    pub target: (f64, f64),
+    /// Mean similarity of the parts, from 0 to 1
+    pub similarity: f32,
+}
+
+impl Repetition {
+    /// Time to add to the source to get to the target
+    #[inline]
+    pub fn offset(&self) -> f64 {
+        self.target.0 - self.source.0
+    }
+}
+
+pub enum RepeatMsg {
+    Done(Vec<Repetition>),
+    Failed(Error)
+}
+
+/// Analyses the samples of the already decoded song on a separate thread
+pub fn find_repetitions(track: Track) -> Receiver<RepeatMsg> {
+    use std::thread;
+
+    let finder = RepetitionFinder::new(
+        config().repetitions.frame_ms,
+        config().repetitions.min_length_secs,
+        config().repetitions.similarity,
+        config().repetitions.max_results);
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        match finder.find(&track) {
+            Ok(repetitions) => tx.send(RepeatMsg::Done(repetitions)).expect("Can't send signal"),
+            Err(e) => tx.send(RepeatMsg::Failed(e)).expect("Can't send signal")
+        }
+    });
+
+    rx
+}
+
+/// Finds repeated parts (like choruses) in the chroma self-similarity matrix.
+/// Every diagonal of the matrix compares the song with itself delayed by
+/// a lag, long runs of similar frames along a diagonal are repetitions
+pub struct RepetitionFinder {
+    frame_ms: f32,
+    min_length_secs: f32,
+    similarity: f32,
+    max_results: usize,
+}
+
+struct Run {
+    start: usize,
+    len: usize,
+    lag: usize,
+    score: f32,
+}
+
+impl RepetitionFinder {
+    pub fn new(frame_ms: f32, min_length_secs: f32, similarity: f32, max_results: usize) -> Self {
+        RepetitionFinder { frame_ms, min_length_secs, similarity, max_results }
+    }
+
+    pub fn find(&self, track: &Track) -> Result<Vec<Repetition>, Error> {
+        ensure!(self.max_results > 0, "Number of repetitions to find must be positive");
+        ensure!(track.sample_rate > 0, "Song has no sample rate");
+
+        let hop = ((track.sample_rate as f32 * self.frame_ms / 1000.0) as usize).max(1);
+        let frame_secs = hop as f64 / track.sample_rate as f64;
+        let chroma = chromagram(&mono(track.channels, &track.samples), track.sample_rate, hop);
+
+        let min_len = ((self.min_length_secs as f64 / frame_secs).ceil() as usize).max(1);
+        ensure!(chroma.len() >= min_len * 2, "Song is too short to find repetitions");
+
+        let mut runs = Vec::new();
+        for lag in min_len..chroma.len() {
+            let diagonal = (0..chroma.len() - lag)
+                .map(|i| similarity(&chroma[i], &chroma[i + lag]))
+                .collect::<Vec<_>>();
+            let diagonal = moving_average(&diagonal, (min_len / 4).max(1));
+            self.collect_runs(&diagonal, lag, min_len, &mut runs);
+        }
+
+        // Neighbouring lags find the same repetition, the strongest one is kept
+        runs.sort_by(|a, b| (b.len as f32 * b.score).partial_cmp(&(a.len as f32 * a.score)).unwrap());
+        let mut accepted: Vec<Run> = Vec::new();
+        for run in runs {
+            let target = (run.start + run.lag, run.start + run.lag + run.len);
+            let taken = accepted.iter()
+                .any(|a| overlap(target, (a.start + a.lag, a.start + a.lag + a.len)) * 2 > run.len);
+            if !taken {
+                accepted.push(run);
+                if accepted.len() >= self.max_results {
+                    break;
+                }
+            }
+        }
+        accepted.sort_by_key(|r| r.start + r.lag);
+
+        let secs = |frame: usize| frame as f64 * frame_secs;
+        Ok(accepted.iter()
+           .map(|r| Repetition {
+               source: (secs(r.start), secs(r.start + r.len)),
+               target: (secs(r.start + r.lag), secs(r.start + r.lag + r.len)),
+               similarity: r.score,
+           })
+           .collect())
+    }
+
+    /// Parts of a diagonal above the similarity threshold lasting at least `min_len` frames.
+    /// A part can't be longer than the lag, the source would overlap the target
+    fn collect_runs(&self, diagonal: &[f32], lag: usize, min_len: usize, runs: &mut Vec<Run>) {
+        let mut start = None;
+        for i in 0..diagonal.len() + 1 {
+            let above = i < diagonal.len() && diagonal[i] >= self.similarity;
+            match (start, above) {
+                (None, true) => start = Some(i),
+                (Some(s), false) => {
+                    let len = (i - s).min(lag);
+                    if len >= min_len {
+                        let score = diagonal[s..s + len].iter().sum::<f32>() / len as f32;
+                        runs.push(Run { start: s, len, lag, score });
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Energy of the twelve pitch classes (C to B) of consecutive frames of `hop` samples
+fn chromagram(samples: &[f32], sample_rate: u32, hop: usize) -> Vec<Chroma> {
+    let window = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect::<Vec<_>>();
+    let classes = (0..FFT_SIZE / 2)
+        .map(|bin| {
+            let freq = bin as f32 * sample_rate as f32 / FFT_SIZE as f32;
+            if freq < MIN_FREQ || freq > MAX_FREQ {
+                return None;
+            }
+            let midi = 69.0 + 12.0 * (freq / 440.0).log2();
+            Some(((midi.round() as i32 % 12 + 12) % 12) as usize)
+        })
+        .collect::<Vec<_>>();
+
+    let mut re = vec![0.0; FFT_SIZE];
+    let mut im = vec![0.0; FFT_SIZE];
+    (0..samples.len() / hop)
+        .map(|frame| {
+            for i in 0..FFT_SIZE {
+                re[i] = samples.get(frame * hop + i).cloned().unwrap_or(0.0) * window[i];
+                im[i] = 0.0;
+            }
+            fft(&mut re, &mut im);
+
+            let mut chroma = [0.0; 12];
+            for (bin, class) in classes.iter().enumerate() {
+                if let Some(class) = *class {
+                    chroma[class] += re[bin] * re[bin] + im[bin] * im[bin];
+                }
+            }
+            normalize(&mut chroma);
+            chroma
+        })
+        .collect()
+}
+
+/// Compresses loudness and removes the mean, so the similarity depends on
+/// the harmony rather than on the volume. Silent frames become zero
+fn normalize(chroma: &mut Chroma) {
+    for c in chroma.iter_mut() {
+        *c = (1.0 + 100.0 * *c).ln();
+    }
+    let mean = chroma.iter().sum::<f32>() / 12.0;
+    for c in chroma.iter_mut() {
+        *c -= mean;
+    }
+    let norm = chroma.iter().map(|c| c * c).sum::<f32>().sqrt();
+    for c in chroma.iter_mut() {
+        *c = if norm > 1e-6 { *c / norm } else { 0.0 };
+    }
+}
+
+/// Cosine similarity of normalized vectors mapped to `[0, 1]`
+fn similarity(a: &Chroma, b: &Chroma) -> f32 {
+    let dot = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>();
+    (dot + 1.0) / 2.0
+}
+
+fn moving_average(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut sums = Vec::with_capacity(values.len() + 1);
+    sums.push(0.0);
+    for v in values {
+        let last = sums[sums.len() - 1];
+        sums.push(last + v);
+    }
+    (0..values.len())
+        .map(|i| {
+            let from = i.saturating_sub(radius);
+            let to = (i + radius + 1).min(values.len());
+            (sums[to] - sums[from]) / (to - from) as f32
+        })
+        .collect()
+}
+
+/// Number of frames shared by two ranges
+fn overlap(a: (usize, usize), b: (usize, usize)) -> usize {
+    a.1.min(b.1).saturating_sub(a.0.max(b.0))
+}
+
+/// In-place radix-2 FFT, the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        let mut start = 0;
+        while start < n {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+            start += len;
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut re = vec![0.0; 8];
+        let mut im = vec![0.0; 8];
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|&r| close(r, 1.0)));
+        assert!(im.iter().all(|&i| close(i, 0.0)));
+    }
+
+    #[test]
+    fn fft_of_a_cosine_peaks_at_its_bins() {
+        let n = 64;
+        let mut re = (0..n).map(|i| (2.0 * PI * 5.0 * i as f32 / n as f32).cos()).collect::<Vec<_>>();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        for bin in 0..n {
+            let magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+            let expected = if bin == 5 || bin == n - 5 { n as f32 / 2.0 } else { 0.0 };
+            assert!(close(magnitude, expected), "bin {}: {}", bin, magnitude);
+        }
+    }
+
+    #[test]
+    fn chromagram_finds_the_pitch_class() {
+        let rate = 44100;
+        let samples = (0..FFT_SIZE * 2)
+            .map(|i| (2.0 * PI * 440.0 * i as f32 / rate as f32).sin())
+            .collect::<Vec<_>>();
+        let chroma = chromagram(&samples, rate, FFT_SIZE);
+        assert_eq!(chroma.len(), 2);
+        for c in &chroma {
+            let loudest = (0..12).max_by(|&a, &b| c[a].partial_cmp(&c[b]).unwrap()).unwrap();
+            assert_eq!(loudest, 9);
+        }
+    }
+
+    #[test]
+    fn silence_is_normalized_to_zero() {
+        let mut chroma = [0.0; 12];
+        normalize(&mut chroma);
+        assert!(chroma.iter().all(|&c| c == 0.0));
+    }
+
+    #[test]
+    fn similarity_of_normalized_vectors() {
+        let mut a = [0.0; 12];
+        a[0] = 1.0;
+        normalize(&mut a);
+        let mut b = a;
+        for c in b.iter_mut() {
+            *c = -*c;
+        }
+        assert!(close(similarity(&a, &a), 1.0));
+        assert!(close(similarity(&a, &b), 0.0));
+    }
+
+    #[test]
+    fn moving_average_shrinks_the_window_at_the_edges() {
+        let average = moving_average(&[1.0, 2.0, 3.0, 4.0, 5.0], 1);
+        assert_eq!(average, vec![1.5, 2.0, 3.0, 4.0, 4.5]);
+        assert_eq!(moving_average(&[1.0, 3.0], 0), vec![1.0, 3.0]);
+        assert!(moving_average(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn overlap_of_ranges() {
+        assert_eq!(overlap((0, 10), (5, 15)), 5);
+        assert_eq!(overlap((5, 15), (0, 10)), 5);
+        assert_eq!(overlap((0, 10), (2, 4)), 2);
+        assert_eq!(overlap((0, 5), (5, 10)), 0);
+        assert_eq!(overlap((0, 5), (7, 10)), 0);
+    }
+}
@@ -0,0 +1,181 @@
+
+use imgui::*;
+use rodio::Source;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use configuration::{self, config};
+use constants::{CLICK_INTERVAL_MS, CALIBRATION_TAPS};
+use console::Logger;
+use song::open_sink;
+
+const CLICK_SAMPLE_RATE: u32 = 44_100;
+const CLICK_LEN_MS: u64 = 20;
+const CLICK_FREQUENCY: f32 = 1000.0;
+
+/// Measures the output latency: the user taps along with a click track and
+/// the median delay of the taps after the clicks becomes `output_latency_ms`
+pub struct Calibration {
+    logger: Logger,
+    started: Option<Instant>,
+    stop: Arc<AtomicBool>,
+    offsets: Vec<f64>,
+}
+
+impl Calibration {
+    pub fn new(logger: Logger) -> Self {
+        Calibration {
+            logger,
+            started: None,
+            stop: Arc::new(AtomicBool::new(true)),
+            offsets: Vec::new(),
+        }
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>) -> bool {
+        let mut opened = true;
+        ui.window(im_str!("Latency calibration"))
+            .opened(&mut opened)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                ui.text(im_str!("Tap in time with the clicks you hear"));
+                if self.started.is_some() {
+                    if ui.button(im_str!("tap"), (120.0, 40.0)) {
+                        self.tap();
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("stop"), (0.0, 0.0)) {
+                        self.stop();
+                    }
+                } else if ui.button(im_str!("start"), (0.0, 0.0)) {
+                    self.start();
+                }
+
+                ui.text(im_str!("current latency: {:.0} ms", config().player.output_latency_ms));
+                match self.latency() {
+                    Some(latency) => {
+                        ui.text(im_str!("measured latency: {:.0} ms", latency));
+                        if ui.button(im_str!("apply"), (0.0, 0.0)) {
+                            self.apply(latency);
+                        }
+                    }
+                    None => ui.text(im_str!("{} of {} taps", self.offsets.len(), CALIBRATION_TAPS))
+                }
+            });
+
+        if !opened {
+            self.stop();
+        }
+        opened
+    }
+
+    fn start(&mut self) {
+        let mut sink = match open_sink(&self.logger) {
+            Some(sink) => sink,
+            None => return self.logger.error("No output device found, can't play the click track")
+        };
+
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.offsets.clear();
+        sink.play(Box::new(ClickTrack::new(self.stop.clone())), Arc::new(|| true));
+        self.started = Some(Instant::now());
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.started = None;
+    }
+
+    /// Taps up to a quarter of the interval before a click count as early
+    fn tap(&mut self) {
+        if let Some(started) = self.started {
+            let elapsed = started.elapsed();
+            let elapsed_ms = elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1e6;
+            let interval = CLICK_INTERVAL_MS as f64;
+            let offset = elapsed_ms % interval;
+            self.offsets.push(if offset > interval * 0.75 { offset - interval } else { offset });
+        }
+    }
+
+    /// Median of the tap offsets, the first taps are skipped while the user
+    /// catches the beat
+    fn latency(&self) -> Option<f64> {
+        if self.offsets.len() < CALIBRATION_TAPS {
+            return None;
+        }
+
+        let mut offsets = self.offsets[2..].to_vec();
+        offsets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Some(offsets[offsets.len() / 2].max(0.0))
+    }
+
+    fn apply(&mut self, latency: f64) {
+        match configuration::update_user(|settings| settings.player.output_latency_ms = latency.round() as f32) {
+            Ok(_) => self.logger.info(format!("Output latency set to {:.0} ms", latency)),
+            Err(e) => self.logger.error(format!("Can't save output latency: {}", e))
+        }
+    }
+}
+
+impl Drop for Calibration {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Short decaying beeps every `CLICK_INTERVAL_MS`, ends when `stop` is set
+struct ClickTrack {
+    index: u64,
+    stop: Arc<AtomicBool>,
+}
+
+impl ClickTrack {
+    fn new(stop: Arc<AtomicBool>) -> Self {
+        ClickTrack { index: 0, stop }
+    }
+}
+
+impl Iterator for ClickTrack {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let rate = CLICK_SAMPLE_RATE as u64;
+        let position = self.index % (rate * CLICK_INTERVAL_MS / 1000);
+        let click_len = rate * CLICK_LEN_MS / 1000;
+        self.index += 1;
+
+        if position < click_len {
+            let t = position as f32 / CLICK_SAMPLE_RATE as f32;
+            let envelope = 1.0 - position as f32 / click_len as f32;
+            Some((2.0 * PI * CLICK_FREQUENCY * t).sin() * 0.5 * envelope)
+        } else {
+            Some(0.0)
+        }
+    }
+}
+
+impl Source for ClickTrack {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        CLICK_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,36 @@
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use state::{LanguageTab, TimeFrame};
+
+#[derive(Clone)]
+pub enum ClipboardData {
+    /// Frames with the id of the window they were copied from, their links
+    /// to lyrics lines are valid only there
+    Frames(usize, Vec<TimeFrame>),
+    Language(LanguageTab)
+}
+
+/// Frames or lyrics copied in one project and pasted into another.
+/// Shared by all open projects like `Logger`
+#[derive(Clone)]
+pub struct Clipboard(Rc<RefCell<Option<ClipboardData>>>);
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Clipboard(Rc::new(RefCell::new(None)))
+    }
+
+    pub fn copy(&mut self, data: ClipboardData) {
+        *self.0.borrow_mut() = Some(data);
+    }
+
+    pub fn paste(&self) -> Option<ClipboardData> {
+        self.0.borrow().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_none()
+    }
+}
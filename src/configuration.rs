@@ -1,40 +1,83 @@
 
-use config::{Config as Config_, File};
+use config::{Config as Config_, Environment, File};
+use failure::Error;
+use toml;
 
-#[derive(Debug)]
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use constants::{BUILTIN_FONT, SETTINGS_FILE, PROJECT_SETTINGS_EXT, ENV_PREFIX};
+use language;
+
+/// How often settings files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Config {
     pub state: State,
     pub dialogs: Dialogs,
     pub player: Player,
     pub console: Console,
     pub main_window: MainWindow,
+    pub alignment: Alignment,
+    pub repetitions: Repetitions,
+    pub export: Export,
+    pub fonts: Fonts,
+    /// Action name to key chord, see `keybindings` for the list of actions
+    pub keybindings: HashMap<String, String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct State {
     pub default_lyrics_text_size: usize,
     pub default_tab_lang: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Dialogs {
     pub base_dir: String,
     pub dialog_sizes: (f32, f32),
     pub file_browser_width: f32,
+    pub file_browser_height: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Player {
     pub player_frame_size: (f32, f32),
     pub default_volume: f32,
+    pub seek_step: u32,
+    /// Name of the output device, the default device is used if it's not set or not connected
+    pub output_device: Option<String>,
+    /// Where the audio goes: `device`, `null` or `wav` to write it to `sink_file`
+    pub sink: String,
+    pub sink_file: String,
+    /// Time between a sample being pulled by the output and being heard
+    pub output_latency_ms: f32,
+    /// Fade in and out on play, stop, pause and seek
+    pub fade_ms: u32,
+    /// Crossfade of the end of a looped time span into its start, zero disables it
+    pub loop_crossfade_ms: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Console {
     pub console_pos: (f32, f32),
+    pub console_size: (f32, f32),
+    /// Number of messages kept in memory
+    pub history_len: usize,
+    /// Messages are also appended to this file if it's set
+    pub log_file: Option<String>,
+    /// Size in bytes after which the log file is rotated
+    pub log_file_size: u64,
+    /// Number of rotated log files kept
+    pub log_files: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MainWindow {
     pub tooltip_len: usize,
     pub lang_name_len: usize,
@@ -46,20 +89,288 @@ pub struct MainWindow {
     pub timeframe_tooltip_width: f32,
     pub new_lang_input_width: f32,
     pub quatrains_frame_size: (f32, f32),
+    pub word_tapper_size: (f32, f32),
+    pub karaoke_color: (f32, f32, f32, f32),
+    pub parallel_editor_size: (f32, f32),
+    pub parallel_line_len: usize,
+    /// Length of a shared section occurrence inserted before any other, in seconds
+    pub new_occurrence_len: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alignment {
+    pub frame_ms: f32,
+    pub min_silence_ms: f32,
+    pub snap_window_ms: f32,
+    pub command: Option<String>,
+}
+
+/// Search for repeated parts of a song, see `analysis::RepetitionFinder`
+#[derive(Debug, Clone, Serialize)]
+pub struct Repetitions {
+    pub frame_ms: f32,
+    pub min_length_secs: f32,
+    /// Similarity from 0 to 1 two parts need to be a repetition
+    pub similarity: f32,
+    pub max_results: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Export {
+    pub font: String,
+    pub font_size: u32,
+    pub play_res: (u32, u32),
+    pub margin_v: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fonts {
+    pub size: f32,
+    pub sources: Vec<FontSource>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FontSource {
+    pub path: String,
+    pub glyph_ranges: Vec<String>,
 }
 
 lazy_static! {
-    pub static ref CONFIG: Config = {
-        let mut config = Config_::new();
-        config.merge(File::with_name("Settings.toml").required(false))
-            .expect("Can't merge settings file path");
+    static ref CONFIG: RwLock<Arc<Config>> = RwLock::new(Arc::new(load(&settings_files()).unwrap_or_default()));
+    static ref PROJECT: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Current settings. The returned snapshot doesn't change when settings are reloaded
+pub fn config() -> Arc<Config> {
+    CONFIG.read().expect("Settings lock is poisoned").clone()
+}
+
+/// Settings of the user settings file over the built-in defaults, without the
+/// working directory, project and environment layers. This is the layer to
+/// edit and `save`, so overrides of the other layers don't become global
+pub fn user_config() -> Result<Config, Error> {
+    let files = user_settings_file().into_iter().collect::<Vec<_>>();
+    read(&files, false)
+}
+
+/// Changes the user settings layer and saves it
+pub fn update_user<F: FnOnce(&mut Config)>(update: F) -> Result<(), Error> {
+    let mut config = user_config()?;
+    update(&mut config);
+    save(config)
+}
+
+/// Validates the user settings layer (see `user_config`), writes it to the user
+/// settings file and reloads all the layers, so project and environment overrides still apply.
+/// Only values which differ from the built-in defaults are written, so changed
+/// defaults still reach users who saved their settings
+pub fn save(config: Config) -> Result<(), Error> {
+    validate(&config)?;
+    let path = user_settings_file().unwrap_or_else(|| PathBuf::from(SETTINGS_FILE));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let defaults = toml::Value::try_from(Config::default())?;
+    let settings = overrides(toml::Value::try_from(config)?, &defaults)
+        .unwrap_or_else(|| toml::Value::Table(Default::default()));
+    fs::write(path, toml::to_string(&settings)?)?;
+    reload()
+}
+
+/// Parts of `value` which differ from `defaults`, tables are compared key by key
+fn overrides(value: toml::Value, defaults: &toml::Value) -> Option<toml::Value> {
+    match (value, defaults) {
+        (toml::Value::Table(table), &toml::Value::Table(ref defaults)) => {
+            let table = table.into_iter()
+                .filter_map(|(key, value)| match defaults.get(&key) {
+                    Some(default) => overrides(value, default).map(|value| (key, value)),
+                    None => Some((key, value))
+                })
+                .collect::<toml::value::Table>();
+            if table.is_empty() { None } else { Some(toml::Value::Table(table)) }
+        }
+        (value, defaults) => if value == *defaults { None } else { Some(value) }
+    }
+}
+
+/// Sets the project whose settings override the user ones. They are applied
+/// on the next `SettingsWatcher::poll`
+pub fn set_project<P: AsRef<Path>>(project: Option<P>) {
+    *PROJECT.write().expect("Project lock is poisoned") = project.map(|p| p.as_ref().to_path_buf());
+}
+
+/// Settings files from the lowest to the highest priority: the user settings,
+/// `Settings.toml` in the working directory and the current project settings
+/// (`<project>.settings.toml` next to the project file). Built-in defaults are
+/// below them and `MELOS_*` environment variables above, so `MELOS_PLAYER__SEEK_STEP=10`
+/// overrides `seek_step` in the `[player]` section
+pub fn settings_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    files.extend(user_settings_file());
+    files.push(PathBuf::from(SETTINGS_FILE));
+    if let Some(ref project) = *PROJECT.read().expect("Project lock is poisoned") {
+        files.push(project.with_extension(PROJECT_SETTINGS_EXT));
+    }
+    files
+}
+
+/// `Settings.toml` in the user directory
+pub fn user_settings_file() -> Option<PathBuf> {
+    user_dir().map(|dir| dir.join(SETTINGS_FILE))
+}
+
+/// `$XDG_CONFIG_HOME/melos` (`~/.config/melos`) or `%APPDATA%\melos`
+pub fn user_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    dir.map(|dir| dir.join("melos"))
+}
+
+/// Rereads all settings layers. Defaults are applied if they can't be read
+pub fn reload() -> Result<(), Error> {
+    match load(&settings_files()) {
+        Ok(config) => {
+            replace(config);
+            Ok(())
+        }
+        Err(e) => {
+            replace(Config::default());
+            Err(e)
+        }
+    }
+}
+
+/// Checks the values which would break windows or dialogs
+pub fn validate(config: &Config) -> Result<(), Error> {
+    ensure!(config.state.default_lyrics_text_size > 0, "Default lyrics text size must be positive");
+    language::canonical_tag(&config.state.default_tab_lang)?;
+    ensure!(Path::new(&config.dialogs.base_dir).is_dir(), "Base directory '{}' doesn't exist", config.dialogs.base_dir);
+    positive("Dialog size", config.dialogs.dialog_sizes)?;
+    positive("File browser size", (config.dialogs.file_browser_width, config.dialogs.file_browser_height))?;
+    positive("Player frame size", config.player.player_frame_size)?;
+    ensure!(config.player.default_volume >= 0.0 && config.player.default_volume <= 100.0,
+            "Default volume must be between 0 and 100");
+    ensure!(config.player.seek_step > 0, "Seek step must be positive");
+    ensure!(["device", "null", "wav"].contains(&config.player.sink.as_str()),
+            "Unknown audio sink '{}', expected device, null or wav", config.player.sink);
+    ensure!(config.player.output_latency_ms >= 0.0, "Output latency can't be negative");
+    ensure!(config.repetitions.frame_ms > 0.0 && config.repetitions.min_length_secs > 0.0,
+            "Repetition frame and length must be positive");
+    ensure!(config.repetitions.similarity >= 0.0 && config.repetitions.similarity <= 1.0,
+            "Repetition similarity must be between 0 and 1");
+    ensure!(config.repetitions.max_results > 0, "Number of repetitions must be positive");
+    ensure!(config.console.console_pos.0 >= 0.0 && config.console.console_pos.1 >= 0.0,
+            "Console position can't be negative");
+    positive("Console size", config.console.console_size)?;
+    ensure!(config.console.history_len > 0, "Console history length must be positive");
+
+    let main_window = &config.main_window;
+    ensure!(main_window.tooltip_len > 0, "Tooltip length must be positive");
+    ensure!(main_window.lang_name_len > 0, "Language name length must be positive");
+    ensure!(main_window.parallel_line_len > 0, "Side by side line length must be positive");
+    ensure!(main_window.new_occurrence_len > 0.0, "New section occurrence length must be positive");
+    positive("Main window size", main_window.main_window_size)?;
+    positive("Quatrains frame size", main_window.quatrains_frame_size)?;
+    positive("Lyrics input size", (main_window.lyrics_input_width, main_window.lyrics_input_height))?;
+    positive("Word tapper size", main_window.word_tapper_size)?;
+    positive("Side by side editor size", main_window.parallel_editor_size)?;
+    ensure!(main_window.column_offset > 0.0 && main_window.song_path_input_len > 0.0 &&
+            main_window.timeframe_tooltip_width > 0.0 && main_window.new_lang_input_width > 0.0,
+            "Main window widths must be positive");
+    Ok(())
+}
+
+/// Reloads settings when one of the settings files or the project changes
+pub struct SettingsWatcher {
+    modified: Option<Vec<(PathBuf, Option<SystemTime>)>>,
+    last_check: Option<Instant>,
+}
+
+impl SettingsWatcher {
+    pub fn new() -> Self {
+        SettingsWatcher {
+            modified: None,
+            last_check: None,
+        }
+    }
+
+    /// Returns the reload result if the files were changed since the last call.
+    /// The first call always reloads, so startup errors are reported too
+    pub fn poll(&mut self) -> Option<Result<(), Error>> {
+        let files = settings_files();
+        let project_changed = self.modified.as_ref()
+            .map_or(true, |m| !m.iter().map(|&(ref p, _)| p).eq(files.iter()));
+        if !project_changed && self.last_check.map_or(false, |t| t.elapsed() < WATCH_INTERVAL) {
+            return None;
+        }
+        self.last_check = Some(Instant::now());
+
+        let modified = files.into_iter()
+            .map(|path| {
+                let time = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, time)
+            })
+            .collect::<Vec<_>>();
+        if self.modified.as_ref() == Some(&modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(reload())
+    }
+}
+
+fn load(files: &[PathBuf]) -> Result<Config, Error> {
+    read(files, true)
+}
+
+fn read(files: &[PathBuf], environment: bool) -> Result<Config, Error> {
+    let mut config = Config_::new();
+    for path in files {
+        config.merge(File::from(path.as_path()).required(false))
+            .map_err(|e| format_err!("{}: {}", path.display(), e))?;
+    }
+    if environment {
+        config.merge(Environment::with_prefix(ENV_PREFIX).separator("__"))?;
+    }
 
-        let map: Settings = config.try_into().expect("Can't parse settings file");
+    let map: Settings = config.try_into()?;
+    let config = Config::from(map);
+    validate(&config)?;
+    Ok(config)
+}
+
+fn replace(config: Config) {
+    *CONFIG.write().expect("Settings lock is poisoned") = Arc::new(config);
+}
+
+fn positive(name: &str, size: (f32, f32)) -> Result<(), Error> {
+    ensure!(size.0 > 0.0 && size.1 > 0.0, "{} must be positive", name);
+    Ok(())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::from(Settings::default())
+    }
+}
+
+impl From<Settings> for Config {
+    fn from(map: Settings) -> Self {
         let state = map.state.unwrap_or(State_::default());
         let dialogs = map.dialogs.unwrap_or(Dialogs_::default());
         let player = map.player.unwrap_or(Player_::default());
         let console = map.console.unwrap_or(Console_::default());
         let main_window = map.main_window.unwrap_or(MainWindow_::default());
+        let alignment = map.alignment.unwrap_or(Alignment_::default());
+        let repetitions = map.repetitions.unwrap_or(Repetitions_::default());
+        let export = map.export.unwrap_or(Export_::default());
+        let fonts = map.fonts.unwrap_or(Fonts_::default());
 
         Config {
             state: State {
@@ -68,39 +379,93 @@ lazy_static! {
             },
             dialogs: Dialogs {
                 base_dir: dialogs.base_dir.unwrap_or(".".into()),
-                dialog_sizes: dialogs.dialog_sizes.unwrap_or((275.0, 165.0)),
-                file_browser_width: dialogs.file_browser_width.unwrap_or(260.0),
+                dialog_sizes: dialogs.dialog_sizes.unwrap_or((560.0, 420.0)),
+                file_browser_width: dialogs.file_browser_width.unwrap_or(540.0),
+                file_browser_height: dialogs.file_browser_height.unwrap_or(300.0),
             },
             player: Player {
-                player_frame_size: player.player_frame_size.unwrap_or((340.0, 105.0)),
-                default_volume: player.default_volume.unwrap_or(50.0)
+                player_frame_size: player.player_frame_size.unwrap_or((340.0, 130.0)),
+                default_volume: player.default_volume.unwrap_or(50.0),
+                seek_step: player.seek_step.unwrap_or(5),
+                output_device: player.output_device,
+                sink: player.sink.unwrap_or_else(|| "device".to_owned()),
+                sink_file: player.sink_file.unwrap_or_else(|| "output.wav".to_owned()),
+                output_latency_ms: player.output_latency_ms.unwrap_or(50.0),
+                fade_ms: player.fade_ms.unwrap_or(10),
+                loop_crossfade_ms: player.loop_crossfade_ms.unwrap_or(50),
             },
             console: Console {
                 console_pos: console.console_pos.unwrap_or((5.0, 25.0)),
+                console_size: console.console_size.unwrap_or((500.0, 150.0)),
+                history_len: console.history_len.unwrap_or(1000),
+                log_file: console.log_file,
+                log_file_size: console.log_file_size.unwrap_or(1024 * 1024),
+                log_files: console.log_files.unwrap_or(3),
             },
             main_window: MainWindow {
                 main_window_size: main_window.main_window_size.unwrap_or((620.0, 565.0)),
                 quatrains_frame_size: main_window.quatrains_frame_size.unwrap_or((340.0, 190.0)),
                 tooltip_len: main_window.tooltip_len.unwrap_or(15),
-                lang_name_len: main_window.lang_name_len.unwrap_or(5),
+                lang_name_len: main_window.lang_name_len.unwrap_or(35),
                 lyrics_input_width: main_window.lyrics_input_width.unwrap_or(550.0),
                 lyrics_input_height: main_window.lyrics_input_height.unwrap_or(530.0),
                 column_offset: main_window.column_offset.unwrap_or(560.0),
                 song_path_input_len: main_window.song_path_input_len.unwrap_or(300.0),
                 timeframe_tooltip_width: main_window.timeframe_tooltip_width.unwrap_or(100.0),
-                new_lang_input_width: main_window.new_lang_input_width.unwrap_or(40.0),
-            }
+                new_lang_input_width: main_window.new_lang_input_width.unwrap_or(80.0),
+                word_tapper_size: main_window.word_tapper_size.unwrap_or((360.0, 150.0)),
+                karaoke_color: main_window.karaoke_color.unwrap_or((1.0, 0.8, 0.2, 1.0)),
+                parallel_editor_size: main_window.parallel_editor_size.unwrap_or((900.0, 500.0)),
+                parallel_line_len: main_window.parallel_line_len.unwrap_or(256),
+                new_occurrence_len: main_window.new_occurrence_len.unwrap_or(10.0),
+            },
+            alignment: Alignment {
+                frame_ms: alignment.frame_ms.unwrap_or(20.0),
+                min_silence_ms: alignment.min_silence_ms.unwrap_or(300.0),
+                snap_window_ms: alignment.snap_window_ms.unwrap_or(250.0),
+                command: alignment.command,
+            },
+            repetitions: Repetitions {
+                frame_ms: repetitions.frame_ms.unwrap_or(250.0),
+                min_length_secs: repetitions.min_length_secs.unwrap_or(8.0),
+                similarity: repetitions.similarity.unwrap_or(0.8),
+                max_results: repetitions.max_results.unwrap_or(16),
+            },
+            export: Export {
+                font: export.font.unwrap_or("Arial".into()),
+                font_size: export.font_size.unwrap_or(48),
+                play_res: export.play_res.unwrap_or((1920, 1080)),
+                margin_v: export.margin_v.unwrap_or(40),
+            },
+            fonts: Fonts {
+                size: fonts.size.unwrap_or(15.0),
+                sources: fonts.sources
+                    .map(|s| s.into_iter().map(|f| FontSource {
+                        path: f.path,
+                        glyph_ranges: f.glyph_ranges.unwrap_or(vec!["default".into()]),
+                    }).collect())
+                    .unwrap_or(vec![FontSource {
+                        path: BUILTIN_FONT.into(),
+                        glyph_ranges: vec!["cyrillic".into()],
+                    }]),
+            },
+            keybindings: map.keybindings.unwrap_or_default(),
         }
-    };
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct Settings {
     state: Option<State_>,
     dialogs: Option<Dialogs_>,
     player: Option<Player_>,
     console: Option<Console_>,
-    main_window: Option<MainWindow_>
+    main_window: Option<MainWindow_>,
+    alignment: Option<Alignment_>,
+    repetitions: Option<Repetitions_>,
+    export: Option<Export_>,
+    fonts: Option<Fonts_>,
+    keybindings: Option<HashMap<String, String>>
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -114,17 +479,30 @@ struct Dialogs_ {
     base_dir: Option<String>,
     dialog_sizes: Option<(f32, f32)>,
     file_browser_width: Option<f32>,
+    file_browser_height: Option<f32>,
 }
 
 #[derive(Debug, Default, Deserialize)]
 struct Player_ {
     player_frame_size: Option<(f32, f32)>,
     default_volume: Option<f32>,
+    seek_step: Option<u32>,
+    output_device: Option<String>,
+    sink: Option<String>,
+    sink_file: Option<String>,
+    output_latency_ms: Option<f32>,
+    fade_ms: Option<u32>,
+    loop_crossfade_ms: Option<u32>,
 }
 
 #[derive(Debug, Default, Deserialize)]
 struct Console_ {
     console_pos: Option<(f32, f32)>,
+    console_size: Option<(f32, f32)>,
+    history_len: Option<usize>,
+    log_file: Option<String>,
+    log_file_size: Option<u64>,
+    log_files: Option<usize>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -139,4 +517,45 @@ struct MainWindow_ {
     timeframe_tooltip_width: Option<f32>,
     new_lang_input_width: Option<f32>,
     quatrains_frame_size: Option<(f32, f32)>,
+    word_tapper_size: Option<(f32, f32)>,
+    karaoke_color: Option<(f32, f32, f32, f32)>,
+    parallel_editor_size: Option<(f32, f32)>,
+    parallel_line_len: Option<usize>,
+    new_occurrence_len: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Alignment_ {
+    frame_ms: Option<f32>,
+    min_silence_ms: Option<f32>,
+    snap_window_ms: Option<f32>,
+    command: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Repetitions_ {
+    frame_ms: Option<f32>,
+    min_length_secs: Option<f32>,
+    similarity: Option<f32>,
+    max_results: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Export_ {
+    font: Option<String>,
+    font_size: Option<u32>,
+    play_res: Option<(u32, u32)>,
+    margin_v: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Fonts_ {
+    size: Option<f32>,
+    sources: Option<Vec<FontSource_>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FontSource_ {
+    path: String,
+    glyph_ranges: Option<Vec<String>>,
 }
@@ -1,35 +1,43 @@
 
+use failure::Error;
 use imgui::*;
 
-use std::rc::Rc;
-use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use configuration::*;
 
+const LEVELS: &[Level] = &[Level::Debug, Level::Info, Level::Warning, Level::Error];
+
 pub struct Console {
     logger: Logger,
-    logs: Vec<String>
+    level: i32,
 }
 
 impl Console {
-    pub fn new(mut logger: Logger) -> Self {
-        logger.enable();
+    pub fn new(logger: Logger) -> Self {
         Console {
             logger,
-            logs: Vec::new() 
+            level: 1,
         }
     }
 
     pub fn show<'a>(&mut self, ui: &Ui<'a>) -> bool {
-        if let Some(mut logs) = self.logger.full_logs() {
-            self.logs.append(&mut logs);
-        }
-
         let mut opened = true;
+        let mut clear = false;
+        let level = LEVELS.get(self.level as usize).cloned().unwrap_or(Level::Info);
+        let names = LEVELS.iter().map(|l| ImString::new(l.to_string())).collect::<Vec<_>>();
+        let names = names.iter().map(|n| n.as_ref()).collect::<Vec<&ImStr>>();
+
         ui.with_style_var(StyleVar::Alpha(0.3), || {
             ui.window(im_str!("##logs"))
-                .position(CONFIG.console.console_pos, ImGuiCond::Always)
+                .position(config().console.console_pos, ImGuiCond::Always)
                 .opened(&mut opened)
                 .collapsible(false)
                 .title_bar(false)
@@ -37,44 +45,294 @@ impl Console {
                 .no_focus_on_appearing(true)
                 .build(|| {
                     ui.with_style_var(StyleVar::Alpha(1.0), || {
-                        self.logs.iter().for_each(|log| ui.text(log));
+                        ui.with_item_width(100.0, || {
+                            ui.combo(im_str!("level"), &mut self.level, &names, 4);
+                        });
+                        ui.same_line(0.0);
+                        clear = ui.button(im_str!("clear"), (0.0, 0.0));
+                        ui.child_frame(im_str!("##records"), config().console.console_size)
+                            .show_scrollbar(true)
+                            .build(|| {
+                                self.logger.with_records(|records| {
+                                    for record in records.iter().filter(|r| r.level >= level) {
+                                        ui.text_colored(record.level.color(), im_str!("{}", record));
+                                    }
+                                });
+                            });
                     });
                 });
         });
 
+        if clear {
+            self.logger.clear();
+        }
+
         opened
     }
 }
 
-impl Drop for Console {
-    fn drop(&mut self) {
-        self.logger.disable();
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub enum Level {
+    Debug,
+    Info,
+    Warning,
+    Error
+}
+
+impl Level {
+    fn color(&self) -> (f32, f32, f32, f32) {
+        match *self {
+            Level::Debug => (0.6, 0.6, 0.6, 1.0),
+            Level::Info => (1.0, 1.0, 1.0, 1.0),
+            Level::Warning => (1.0, 0.8, 0.3, 1.0),
+            Level::Error => (1.0, 0.3, 0.3, 1.0)
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error"
+        })
+    }
+}
+
+pub struct Record {
+    pub level: Level,
+    pub time: SystemTime,
+    pub message: String,
+}
+
+impl fmt::Display for Record {
+    /// `12:30:05Z [warning] message`, the time is UTC and marked so
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        write!(f, "{:02}:{:02}:{:02}Z [{}] {}",
+               secs / 3600 % 24, secs / 60 % 60, secs % 60, self.level, self.message)
+    }
+}
+
+/// Error which must be shown to the user in the error dialog
+pub struct Report {
+    pub message: String,
+    /// Causes of the error, the innermost last
+    pub details: Vec<String>,
+}
+
+impl Report {
+    pub fn new(error: &Error) -> Self {
+        Report {
+            message: error.to_string(),
+            details: error.causes().skip(1).map(|c| c.to_string()).collect(),
+        }
     }
 }
 
+/// Keeps the last messages (`history_len` in the `[console]` section of settings)
+/// whether the console is shown or not, and appends them to the log file if it's set.
+/// Clones share the same log and can be sent to the decoder and audio threads.
+/// Logging only sends the record, the UI thread stores it and writes the file,
+/// so the audio thread never waits for the log lock or the disk
 #[derive(Clone)]
-pub struct Logger(Rc<RefCell<Option<VecDeque<String>>>>);
+pub struct Logger {
+    sender: Sender<Entry>,
+    log: Arc<Mutex<Log>>,
+}
+
+enum Entry {
+    Record(Record),
+    Report(Report),
+}
+
+struct Log {
+    receiver: Receiver<Entry>,
+    records: VecDeque<Record>,
+    capacity: usize,
+    file: Option<LogFile>,
+    reports: Vec<Report>,
+}
 
 impl Logger {
     pub fn new() -> Self {
-        Logger(Rc::new(RefCell::new(None)))
+        let config = config();
+        let capacity = config.console.history_len.max(1);
+        let file = config.console.log_file.as_ref()
+            .map(|path| LogFile::new(path.into(), config.console.log_file_size, config.console.log_files));
+
+        let (sender, receiver) = channel();
+        Logger {
+            sender,
+            log: Arc::new(Mutex::new(Log {
+                receiver,
+                records: VecDeque::with_capacity(capacity),
+                capacity,
+                file,
+                reports: Vec::new(),
+            })),
+        }
     }
 
-    pub fn enable(&mut self) {
-        *self.0.borrow_mut() = Some(VecDeque::new());
+    pub fn log<T: ToString>(&mut self, level: Level, message: T) {
+        let record = Record {
+            level,
+            time: SystemTime::now(),
+            message: message.to_string(),
+        };
+
+        // the log is gone only while the application exits
+        let _ = self.sender.send(Entry::Record(record));
+    }
+
+    #[inline]
+    pub fn debug<T: ToString>(&mut self, message: T) {
+        self.log(Level::Debug, message);
     }
 
-    pub fn disable(&mut self) {
-        *self.0.borrow_mut() = None;
+    #[inline]
+    pub fn info<T: ToString>(&mut self, message: T) {
+        self.log(Level::Info, message);
     }
 
-    pub fn log<T: ToString>(&mut self, log: T) {
-        if let Some(ref mut queue) = *self.0.borrow_mut() {
-            queue.push_back(log.to_string());
+    #[inline]
+    pub fn warn<T: ToString>(&mut self, message: T) {
+        self.log(Level::Warning, message);
+    }
+
+    #[inline]
+    pub fn error<T: ToString>(&mut self, message: T) {
+        self.log(Level::Error, message);
+    }
+
+    /// Logs the error with its causes and queues it for the error dialog
+    pub fn report(&mut self, error: &Error) {
+        let report = Report::new(error);
+        let mut message = report.message.clone();
+        for detail in &report.details {
+            message.push_str(": ");
+            message.push_str(detail);
         }
+        self.error(message);
+        let _ = self.sender.send(Entry::Report(report));
+    }
+
+    /// Errors reported since the last call
+    pub fn take_reports(&mut self) -> Vec<Report> {
+        ::std::mem::replace(&mut self.drain().reports, Vec::new())
+    }
+
+    pub fn with_records<F: FnOnce(&VecDeque<Record>)>(&self, f: F) {
+        f(&self.drain().records)
+    }
+
+    pub fn clear(&mut self) {
+        self.drain().records.clear();
     }
 
-    pub fn full_logs(&mut self) -> Option<Vec<String>> {
-        self.0.borrow_mut().as_mut().map(|x| x.drain(..).collect())
+    /// Stores the records sent since the last call and writes them to the log
+    /// file. Called by the UI thread every frame, whether the console is shown or not
+    #[inline]
+    pub fn flush(&self) {
+        self.drain();
     }
+
+    fn drain(&self) -> MutexGuard<Log> {
+        let mut guard = self.lock();
+        {
+            let log = &mut *guard;
+            while let Ok(entry) = log.receiver.try_recv() {
+                match entry {
+                    Entry::Record(record) => {
+                        if let Some(ref mut file) = log.file {
+                            file.write(&record);
+                        }
+                        if log.records.len() >= log.capacity {
+                            log.records.pop_front();
+                        }
+                        log.records.push_back(record);
+                    }
+                    Entry::Report(report) => log.reports.push(report)
+                }
+            }
+        }
+        guard
+    }
+
+    /// A thread which panicked while logging doesn't take the log down with it
+    fn lock(&self) -> MutexGuard<Log> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Log file which is renamed to `<name>.1` (and older ones to `<name>.2`, ...)
+/// once it grows over `max_size` bytes. Write errors are ignored, logging
+/// must not break the application
+struct LogFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl LogFile {
+    fn new(path: PathBuf, max_size: u64, keep: usize) -> Self {
+        LogFile { path, max_size, keep, file: None, size: 0 }
+    }
+
+    fn write(&mut self, record: &Record) {
+        if self.file.is_none() {
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path).ok();
+            self.size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        }
+
+        let secs = record.time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let (year, month, day) = civil_date(secs / 86400);
+        // ISO 8601 in UTC: 2018-05-01T12:30:05Z [warning] message
+        let line = format!("{}-{:02}-{:02}T{}\n", year, month, day, record);
+        if let Some(ref mut file) = self.file {
+            if file.write_all(line.as_bytes()).is_ok() {
+                self.size += line.len() as u64;
+            }
+        }
+
+        if self.size >= self.max_size {
+            self.rotate();
+        }
+    }
+
+    fn rotate(&mut self) {
+        self.file = None;
+        if self.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+            return;
+        }
+
+        let rotated = |idx: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", idx));
+            PathBuf::from(name)
+        };
+        for idx in (1..self.keep).rev() {
+            let _ = fs::rename(rotated(idx), rotated(idx + 1));
+        }
+        let _ = fs::rename(&self.path, rotated(1));
+    }
+}
+
+/// Year, month and day of a day counted from 1970-01-01
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
 }
@@ -1,5 +1,32 @@
 
 pub const MAX_PATH_LEN: usize = 256;
-pub const SAVE_FILE_EXT_FILTER: &str = "*.json";
 pub const SAVE_FILE_EXT: &str = "json";
+pub const ASS_FILE_EXT: &str = "ass";
+pub const MIN_PARALLEL_LANGUAGES: usize = 2;
+pub const MAX_PARALLEL_LANGUAGES: usize = 4;
+/// Font path which refers to the font bundled into the executable
+pub const BUILTIN_FONT: &str = "builtin";
 
+pub const SETTINGS_FILE: &str = "Settings.toml";
+/// Extension of project settings, `song.json` is overridden by `song.settings.toml`
+pub const PROJECT_SETTINGS_EXT: &str = "settings.toml";
+/// Prefix of environment variables overriding settings
+pub const ENV_PREFIX: &str = "MELOS";
+pub const SESSION_FILE: &str = "session.json";
+/// Window layout saved by imgui
+pub const LAYOUT_FILE: &str = "imgui.ini";
+pub const MAX_RECENT_PROJECTS: usize = 10;
+/// How often the audio thread checks for underruns and timing drift, in milliseconds
+pub const MONITOR_INTERVAL: u64 = 100;
+/// Gap between two checks which is reported as a buffer underrun, in milliseconds
+pub const UNDERRUN_THRESHOLD: u64 = 300;
+/// Difference between the wall clock and played samples reported as drift, in milliseconds
+pub const DRIFT_THRESHOLD: u64 = 200;
+/// How often the default output device is checked, in milliseconds
+pub const DEVICE_POLL_INTERVAL: u64 = 2000;
+/// How often the output thread checks for a device chosen by the user, in milliseconds
+pub const RECONNECT_CHECK_INTERVAL: u64 = 100;
+/// Time between clicks of the latency calibration track
+pub const CLICK_INTERVAL_MS: u64 = 1000;
+/// Taps needed before the calibrated latency can be applied
+pub const CALIBRATION_TAPS: usize = 8;
@@ -1,38 +1,42 @@
 
-use failure::{Error, err_msg};
+use failure::Error;
 use imgui::*;
-use ignore::WalkBuilder;
-use ignore::overrides::OverrideBuilder;
 
+use std::collections::VecDeque;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use serde_json;
 
 use state::AppData;
 use constants::*;
-use configuration::CONFIG;
-use console::Logger;
+use configuration::config;
+use console::{Logger, Report};
+use errors::AppError;
+use export::write_ass_to_file;
+use file_browser::{FileBrowser, FileFilter, PROJECT_FILES, SUBTITLE_FILES, AUDIO_FILES, ALL_FILES};
 
 pub struct OpenFileDialog {
     logger: Logger,
     path: ImString,
-    cached_paths: Vec<ImString>,
-    selected_item: i32,
+    browser: FileBrowser,
 }
 
 pub enum OpenFileState {
     Displaying,
     Closed,
-    Opened(AppData)
+    Opened(AppData, PathBuf)
 }
 
+static PROJECT_FILTERS: &[&FileFilter] = &[&PROJECT_FILES, &ALL_FILES];
+static SUBTITLE_FILTERS: &[&FileFilter] = &[&SUBTITLE_FILES, &ALL_FILES];
+static AUDIO_FILTERS: &[&FileFilter] = &[&AUDIO_FILES, &ALL_FILES];
+
 impl OpenFileDialog {
     pub fn new(logger: Logger) -> Self {
         OpenFileDialog {
+            browser: FileBrowser::new(logger.clone(), None::<&Path>, PROJECT_FILTERS),
             logger,
             path: ImString::with_capacity(MAX_PATH_LEN),
-            cached_paths: enumerate_files(),
-            selected_item: 0,
         }
     }
 
@@ -40,7 +44,7 @@ impl OpenFileDialog {
         let mut opened = true;
         let mut state = OpenFileState::Displaying;
         ui.window(im_str!("Open File"))
-            .size(CONFIG.dialogs.dialog_sizes, ImGuiCond::Always)
+            .size(config().dialogs.dialog_sizes, ImGuiCond::Always)
             .opened(&mut opened)
             .collapsible(false)
             .resizable(false)
@@ -48,141 +52,266 @@ impl OpenFileDialog {
                 ui.input_text(im_str!("##path"), &mut self.path).build();
                 ui.same_line(0.0);
                 if ui.button(im_str!("open"), (0.0, 0.0)) {
-                    match read_state_from_file(self.path.to_str()) {
-                        Ok(data) => state = OpenFileState::Opened(data),
-                        Err(e) => self.logger.log(format!("{}", e))
+                    let path = self.browser.resolve(self.path.to_str()).with_extension(SAVE_FILE_EXT);
+                    match read_state_from_file(&path) {
+                        Ok(data) => state = OpenFileState::Opened(data, path),
+                        Err(e) => self.logger.report(&e)
                     }
                 }
-                ui.with_item_width(CONFIG.dialogs.file_browser_width, || self.show_file_browser(ui));
+                if let Some(path) = self.browser.show(ui) {
+                    set_path(&mut self.path, &path);
+                }
             });
 
         if opened { state } else { OpenFileState::Closed }
     }
+}
 
-    pub fn update_cached_paths(&mut self) {
-        self.cached_paths = enumerate_files();
-    }
+#[derive(Copy, Clone, PartialEq)]
+pub enum FileFormat {
+    Project,
+    Ass
+}
 
-    fn show_file_browser<'a>(&mut self, ui: &Ui<'a>) {
-        if self.cached_paths.is_empty() {
-            self.update_cached_paths();
+impl FileFormat {
+    fn filters(&self) -> &'static [&'static FileFilter] {
+        match *self {
+            FileFormat::Project => PROJECT_FILTERS,
+            FileFormat::Ass => SUBTITLE_FILTERS
         }
+    }
 
-        let old_selection = self.selected_item;
-        let rpath = self.cached_paths.iter()
-            .map(|x| x.as_ref())
-            .collect::<Vec<_>>();
-
-        ui.list_box(im_str!("##files"), &mut self.selected_item, rpath.as_slice(), 5);
+    fn extension(&self) -> &'static str {
+        match *self {
+            FileFormat::Project => SAVE_FILE_EXT,
+            FileFormat::Ass => ASS_FILE_EXT
+        }
+    }
 
-        if let Some(ref p) = self.cached_paths.get(self.selected_item as usize) {
-            if old_selection != self.selected_item ||
-               self.path.to_str().is_empty() 
-            {
-                self.path.clear();
-                self.path.push_str(p.as_ref());
-            }
+    fn write<P: AsRef<Path>>(&self, data: AppData, path: P, logger: &mut Logger) -> Result<(), Error> {
+        match *self {
+            FileFormat::Project => write_state_to_file(data, path),
+            FileFormat::Ass => write_ass_to_file(data, path, logger)
         }
     }
 }
 
 pub struct SaveFileDialog {
     logger: Logger,
+    format: FileFormat,
     path: ImString,
-    cached_paths: Vec<ImString>,
+    browser: FileBrowser,
+    overwrite: Option<PathBuf>,
+    saved_project: Option<PathBuf>,
 }
 
 impl SaveFileDialog {
     pub fn new(logger: Logger) -> Self {
+        SaveFileDialog::with_format(logger, FileFormat::Project)
+    }
+
+    pub fn with_format(logger: Logger, format: FileFormat) -> Self {
         SaveFileDialog {
+            browser: FileBrowser::new(logger.clone(), None::<&Path>, format.filters()),
             logger,
+            format,
             path: ImString::with_capacity(MAX_PATH_LEN),
-            cached_paths: enumerate_files(),
+            overwrite: None,
+            saved_project: None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.format {
+            FileFormat::Project => "Save File",
+            FileFormat::Ass => "Export Subtitles"
         }
     }
 
-    pub fn show<'a, F>(&mut self, ui: &Ui<'a>, get_data: F) -> bool 
+    /// Existing files are overwritten only after a confirmation
+    pub fn show<'a, F>(&mut self, ui: &Ui<'a>, title: &ImStr, get_data: F) -> bool 
         where F: FnOnce() -> AppData
     {
         let mut opened = true;
         let mut saved = false;
-        ui.window(im_str!("Save File"))
-            .size(CONFIG.dialogs.dialog_sizes, ImGuiCond::Always)
+        ui.window(title)
+            .size(config().dialogs.dialog_sizes, ImGuiCond::Always)
             .opened(&mut opened)
             .collapsible(false)
             .resizable(false)
             .build(|| {
+                let mut target = None;
                 ui.input_text(im_str!("##path"), &mut self.path).build();
                 ui.same_line(0.0);
                 if ui.button(im_str!("save"), (0.0, 0.0)) {
-                    match write_state_to_file(get_data(), self.path.to_str()) {
+                    let path = self.browser.resolve(self.path.to_str()).with_extension(self.format.extension());
+                    if path.exists() {
+                        self.overwrite = Some(path);
+                    } else {
+                        target = Some(path);
+                    }
+                }
+
+                if let Some(path) = self.overwrite.clone() {
+                    ui.text_colored((1.0, 0.8, 0.3, 1.0), im_str!("{} exists. Overwrite?", path.display()));
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("yes"), (0.0, 0.0)) {
+                        target = Some(path);
+                        self.overwrite = None;
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("no"), (0.0, 0.0)) {
+                        self.overwrite = None;
+                    }
+                }
+
+                if let Some(path) = target {
+                    match self.format.write(get_data(), &path, &mut self.logger) {
                         Ok(_) => {
                             saved = true;
-                            self.logger.log("Project saved successfully");
+                            if self.format == FileFormat::Project {
+                                self.saved_project = Some(path);
+                            }
+                            self.logger.info(match self.format {
+                                FileFormat::Project => "Project saved successfully",
+                                FileFormat::Ass => "Subtitles exported successfully"
+                            });
                         },
-                        Err(e) => self.logger.log(format!("{}", e))
+                        Err(e) => self.logger.report(&e)
                     }
-                    self.update_cached_paths();
                 }
-                ui.with_item_width(CONFIG.dialogs.file_browser_width, || self.show_file_browser(ui));
+
+                if let Some(path) = self.browser.show(ui) {
+                    set_path(&mut self.path, &path);
+                    self.overwrite = None;
+                }
             });
         opened ^ saved
     }
 
-    pub fn update_cached_paths(&mut self) {
-        self.cached_paths = enumerate_files();
+    /// Path of the project file if the project was saved
+    pub fn saved_project(&self) -> Option<&Path> {
+        self.saved_project.as_ref().map(|p| p.as_path())
     }
+}
 
-    fn show_file_browser<'a>(&mut self, ui: &Ui<'a>) {
-        if self.cached_paths.is_empty() {
-            self.update_cached_paths();
+pub enum SongFileState {
+    Displaying,
+    Closed,
+    Selected(PathBuf)
+}
+
+/// Picks the song of a project
+pub struct SongFileDialog {
+    path: ImString,
+    browser: FileBrowser,
+}
+
+impl SongFileDialog {
+    /// Starts in the directory of the current song
+    pub fn new(logger: Logger, song: &str) -> Self {
+        SongFileDialog {
+            path: ImString::with_capacity(MAX_PATH_LEN),
+            browser: FileBrowser::new(logger, Path::new(song).parent(), AUDIO_FILTERS),
         }
+    }
 
-        let rpath = self.cached_paths.iter()
-            .map(|x| x.as_ref())
-            .collect::<Vec<_>>();
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr) -> SongFileState {
+        let mut opened = true;
+        let mut state = SongFileState::Displaying;
+        ui.window(title)
+            .size(config().dialogs.dialog_sizes, ImGuiCond::Always)
+            .opened(&mut opened)
+            .collapsible(false)
+            .resizable(false)
+            .build(|| {
+                ui.input_text(im_str!("##path"), &mut self.path).build();
+                ui.same_line(0.0);
+                if ui.button(im_str!("select"), (0.0, 0.0)) && !self.path.to_str().is_empty() {
+                    state = SongFileState::Selected(self.browser.resolve(self.path.to_str()));
+                }
+                if let Some(path) = self.browser.show(ui) {
+                    set_path(&mut self.path, &path);
+                }
+            });
 
-        ui.list_box(im_str!("##files"), &mut 0, rpath.as_slice(), 5);
+        if opened { state } else { SongFileState::Closed }
     }
 }
 
-fn read_state_from_file<P: AsRef<Path>>(path: P) -> Result<AppData, Error> {
+/// Modal window showing reported errors one by one with their causes
+pub struct ErrorDialog {
+    logger: Logger,
+    reports: VecDeque<Report>,
+}
+
+impl ErrorDialog {
+    pub fn new(logger: Logger) -> Self {
+        ErrorDialog { logger, reports: VecDeque::new() }
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>) {
+        self.reports.extend(self.logger.take_reports());
+        if self.reports.is_empty() {
+            return;
+        }
+
+        let mut dismissed = false;
+        ui.open_popup(im_str!("Error"));
+        ui.popup_modal(im_str!("Error"))
+            .always_auto_resize(true)
+            .build(|| {
+                if let Some(report) = self.reports.front() {
+                    ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("{}", report.message));
+                    if !report.details.is_empty() {
+                        ui.separator();
+                        for detail in &report.details {
+                            ui.text(im_str!("{}", detail));
+                        }
+                    }
+                }
+                ui.separator();
+                if self.reports.len() > 1 {
+                    ui.text(im_str!("{} more errors", self.reports.len() - 1));
+                }
+                if ui.button(im_str!("ok"), (80.0, 0.0)) {
+                    dismissed = true;
+                    ui.close_current_popup();
+                }
+            });
+
+        if dismissed {
+            self.reports.pop_front();
+        }
+    }
+}
+
+pub fn read_state_from_file<P: AsRef<Path>>(path: P) -> Result<AppData, Error> {
     use std::io::Read;
 
     let path = path.as_ref().with_extension(SAVE_FILE_EXT);
-    ensure!(path.exists(), "Path is invalid");
+    if !path.exists() {
+        return Err(AppError::NotFound(path).into());
+    }
 
-    let mut file = File::open(path).map_err(|_| err_msg("Can't open file"))?;
-    let metadata = file.metadata().map_err(|_| err_msg("Can't get file metadata"))?;
+    let mut file = File::open(&path).map_err(|e| AppError::Open(path.clone(), e))?;
+    let metadata = file.metadata().map_err(|e| AppError::Read(path.clone(), e))?;
     let mut json = String::with_capacity(metadata.len() as usize);
-    file.read_to_string(&mut json).map_err(|_| err_msg("Can't read save file"))?;
-    serde_json::from_str::<AppData>(&json).map_err(|_| err_msg("Can't deserialize project data"))
+    file.read_to_string(&mut json).map_err(|e| AppError::Read(path.clone(), e))?;
+    Ok(serde_json::from_str::<AppData>(&json).map_err(|e| AppError::Parse(path, e))?)
 }
 
 fn write_state_to_file<P: AsRef<Path>>(state: AppData, path: P) -> Result<(), Error> {
     use std::io::Write;
 
     let path = path.as_ref().with_extension(SAVE_FILE_EXT);
-    let mut file = File::create(path).map_err(|_| err_msg("Could not create file"))?;
-    let json = serde_json::to_string(&state).map_err(|_| err_msg("Can't serialize project data"))?;
-    file.write(json.as_bytes())
-        .map(|_| ())
-        .map_err(|_| err_msg("Can't save project to file"))
+    let mut file = File::create(&path).map_err(|e| AppError::Open(path.clone(), e))?;
+    let json = serde_json::to_string(&state).map_err(AppError::Serialize)?;
+    file.write_all(json.as_bytes()).map_err(|e| AppError::Write(path, e))?;
+    Ok(())
 }
 
-fn enumerate_files() -> Vec<ImString> {
-    let filters = OverrideBuilder::new(&CONFIG.dialogs.base_dir)
-        .add(SAVE_FILE_EXT_FILTER).expect("Save file filter is invalid")
-        .build().expect("Can't build filters");
-
-    WalkBuilder::new(&CONFIG.dialogs.base_dir)
-        .max_depth(Some(1))
-        .standard_filters(true)
-        .overrides(filters)
-        .build()
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file())
-        .map(|e| ImString::new(e.path().to_str().expect("Can't cast path to str")))
-        .collect()
+fn set_path(buf: &mut ImString, path: &Path) {
+    buf.clear();
+    buf.push_str(&path.to_string_lossy());
 }
@@ -0,0 +1,49 @@
+
+use failure::Fail;
+use rodio::decoder::DecoderError;
+use serde_json;
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Failures of reading and writing projects and of loading songs.
+/// The underlying error is kept as the cause and shown as details
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(PathBuf),
+    Open(PathBuf, io::Error),
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    /// Keeps the line and column where the project is broken
+    Parse(PathBuf, serde_json::Error),
+    Serialize(serde_json::Error),
+    Decode(PathBuf, DecoderError),
+    NoOutputDevice,
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AppError::NotFound(ref path) => write!(f, "File {} not found", path.display()),
+            AppError::Open(ref path, _) => write!(f, "Can't open {}", path.display()),
+            AppError::Read(ref path, _) => write!(f, "Can't read {}", path.display()),
+            AppError::Write(ref path, _) => write!(f, "Can't write {}", path.display()),
+            AppError::Parse(ref path, _) => write!(f, "{} isn't a valid project", path.display()),
+            AppError::Serialize(_) => write!(f, "Can't serialize project data"),
+            AppError::Decode(ref path, _) => write!(f, "Can't decode {}", path.display()),
+            AppError::NoOutputDevice => write!(f, "No audio output device found"),
+        }
+    }
+}
+
+impl Fail for AppError {
+    fn cause(&self) -> Option<&Fail> {
+        match *self {
+            AppError::Open(_, ref e) | AppError::Read(_, ref e) | AppError::Write(_, ref e) => Some(e),
+            AppError::Parse(_, ref e) | AppError::Serialize(ref e) => Some(e),
+            AppError::Decode(_, ref e) => Some(e),
+            AppError::NotFound(_) | AppError::NoOutputDevice => None,
+        }
+    }
+}
@@ -0,0 +1,253 @@
+
+use failure::Error;
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+
+use state::{AppData, LanguageTab, TimeFrame, WordTiming, Position, Role, Section};
+use configuration::config;
+use console::Logger;
+use constants::ASS_FILE_EXT;
+use errors::AppError;
+
+pub fn write_ass_to_file<P: AsRef<Path>>(data: AppData, path: P, logger: &mut Logger) -> Result<(), Error> {
+    let path = path.as_ref().with_extension(ASS_FILE_EXT);
+    let file = File::create(&path).map_err(|e| AppError::Open(path.clone(), e))?;
+    let skipped = write_ass(&mut BufWriter::new(file), &data).map_err(|e| AppError::Write(path, e))?;
+    if skipped > 0 {
+        logger.warn(format!("{} frames without a lyrics line, section or tooltip were not exported", skipped));
+    }
+    Ok(())
+}
+
+/// Writes Advanced SubStation Alpha subtitles: a style per language tab and
+/// a dialogue line per time frame and language. Frames with word timings
+/// get karaoke tags in the original language. Returns the number of frames
+/// skipped because they have no text in any language
+pub fn write_ass<W: Write>(out: &mut W, data: &AppData) -> io::Result<usize> {
+    writeln!(out, "[Script Info]")?;
+    writeln!(out, "ScriptType: v4.00+")?;
+    writeln!(out, "PlayResX: {}", config().export.play_res.0)?;
+    writeln!(out, "PlayResY: {}", config().export.play_res.1)?;
+    writeln!(out, "WrapStyle: 0")?;
+    if let Some(original) = data.lyrics.iter().find(|t| t.role == Role::Original) {
+        writeln!(out, "Language: {}", original.lang)?;
+    }
+    for tab in &data.lyrics {
+        writeln!(out, "; {}: {} ({}{})",
+                 tab.lang,
+                 tab.name,
+                 if tab.role == Role::Original { "original" } else { "translation" },
+                 if tab.is_rtl() { ", right-to-left" } else { "" })?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "[V4+ Styles]")?;
+    writeln!(out, "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+                   BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+                   BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding")?;
+    for (idx, tab) in data.lyrics.iter().enumerate() {
+        let style = tab.style();
+        writeln!(out, "Style: {},{},{},{},&H00808080,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,1,{},20,20,{},1",
+                 style_name(idx, tab),
+                 style.font,
+                 style.size,
+                 ass_color(style.color),
+                 alignment(style.position),
+                 config().export.margin_v)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "[Events]")?;
+    writeln!(out, "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text")?;
+    let offset = data.offset_ms as f64 / 1000.0;
+    let mut skipped = 0;
+    for frame in &data.timings {
+        let mut exported = false;
+        for (idx, tab) in data.lyrics.iter().enumerate() {
+            let line = match frame_text(frame, tab, &data.sections) {
+                Some(line) => line,
+                None => continue
+            };
+            exported = true;
+            let text = if tab.role == Role::Original && !frame.words.is_empty() {
+                karaoke_text(frame)
+            } else {
+                escape(line)
+            };
+
+            writeln!(out, "Dialogue: 0,{},{},{},,0,0,0,,{}",
+                     ass_time(frame.start_secs() + offset),
+                     ass_time(frame.end_secs() + offset),
+                     style_name(idx, tab),
+                     text)?;
+        }
+        if !exported {
+            skipped += 1;
+        }
+    }
+
+    Ok(skipped)
+}
+
+/// Text of the frame in the tab. The original language falls back to the frame
+/// tooltip, which is the only text of frames without a line or a section
+/// (added by hand or saved before frames were linked to lines)
+fn frame_text<'a>(frame: &'a TimeFrame, tab: &'a LanguageTab, sections: &'a [Section]) -> Option<&'a str> {
+    match frame.text(&tab.lang, &tab.text, sections) {
+        Some(line) if !line.trim().is_empty() => Some(line),
+        _ if tab.role == Role::Original => frame.tooltip.as_ref()
+            .map(|t| t.as_str())
+            .filter(|t| !t.trim().is_empty()),
+        _ => None
+    }
+}
+
+fn style_name(idx: usize, tab: &LanguageTab) -> String {
+    let lang = tab.lang.chars().filter(|c| c.is_alphanumeric() || *c == '-').collect::<String>();
+    format!("{}_{}", idx, lang)
+}
+
+/// ASS colors are `&HAABBGGRR`
+fn ass_color(color: [f32; 3]) -> String {
+    let channel = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    format!("&H00{:02X}{:02X}{:02X}", channel(color[2]), channel(color[1]), channel(color[0]))
+}
+
+/// Numpad-like alignment, centered horizontally
+fn alignment(position: Position) -> u8 {
+    match position {
+        Position::Top => 8,
+        Position::Bottom => 2
+    }
+}
+
+fn ass_time(secs: f64) -> String {
+    let cs = (secs.max(0.0) * 100.0).round() as u64;
+    format!("{}:{:02}:{:02}.{:02}", cs / 360_000, cs / 6000 % 60, cs / 100 % 60, cs % 100)
+}
+
+/// Braces start override tags, line breaks of shared sections become forced breaks
+fn escape(text: &str) -> String {
+    text.replace('{', "(").replace('}', ")").replace('\n', "\\N")
+}
+
+/// Every syllable (or word without syllables) lasts until the next one starts
+fn karaoke_text(frame: &TimeFrame) -> String {
+    let units = frame.words.iter()
+        .flat_map(|w| {
+            let last = w.syllables.len().max(1) - 1;
+            let syllables: Vec<(&WordTiming, bool)> = if w.syllables.is_empty() {
+                vec![(w, true)]
+            } else {
+                w.syllables.iter().enumerate().map(|(i, s)| (s, i == last)).collect()
+            };
+            syllables
+        })
+        .collect::<Vec<_>>();
+
+    let mut text = String::new();
+    let lead = units.first().map(|&(u, _)| u.start - frame.start_secs()).unwrap_or(0.0);
+    if lead > 0.0 {
+        text.push_str(&format!("{{\\k{}}}", centiseconds(lead)));
+    }
+    for (idx, &(unit, word_end)) in units.iter().enumerate() {
+        let next = units.get(idx + 1).map(|&(u, _)| u.start).unwrap_or(unit.end);
+        text.push_str(&format!("{{\\k{}}}{}", centiseconds(next - unit.start), escape(&unit.text)));
+        if word_end && idx + 1 < units.len() {
+            text.push(' ');
+        }
+    }
+    text
+}
+
+#[inline]
+fn centiseconds(secs: f64) -> u64 {
+    (secs.max(0.0) * 100.0).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state::from_secs;
+
+    fn tab(role: Role, text: &str) -> LanguageTab {
+        LanguageTab {
+            lang: "en".into(),
+            text: text.into(),
+            name: "English".into(),
+            role,
+            rtl: None,
+            style: None,
+        }
+    }
+
+    fn frame(start: f64, end: f64) -> TimeFrame {
+        TimeFrame { start: from_secs(start), end: from_secs(end), .. Default::default() }
+    }
+
+    fn word(text: &str, start: f64, end: f64) -> WordTiming {
+        WordTiming { text: text.into(), start, end, syllables: Vec::new() }
+    }
+
+    #[test]
+    fn ass_time_has_centiseconds() {
+        assert_eq!(ass_time(0.0), "0:00:00.00");
+        assert_eq!(ass_time(83.456), "0:01:23.46");
+        assert_eq!(ass_time(3725.0), "1:02:05.00");
+        assert_eq!(ass_time(-1.0), "0:00:00.00");
+    }
+
+    #[test]
+    fn ass_color_is_blue_green_red() {
+        assert_eq!(ass_color([1.0, 0.5, 0.0]), "&H000080FF");
+    }
+
+    #[test]
+    fn escape_replaces_braces_and_line_breaks() {
+        assert_eq!(escape("{a}\nb"), "(a)\\Nb");
+    }
+
+    #[test]
+    fn karaoke_syllables_last_until_the_next_one() {
+        let mut frame = frame(1.0, 3.0);
+        let mut word_a = word("hel", 1.5, 2.0);
+        word_a.syllables = vec![word("hel", 1.5, 1.75), word("lo", 1.75, 2.0)];
+        frame.words = vec![word_a, word("you", 2.25, 2.75)];
+        assert_eq!(karaoke_text(&frame), "{\\k50}{\\k25}hel{\\k50}lo {\\k50}you");
+    }
+
+    #[test]
+    fn unlinked_frames_use_only_the_tooltip() {
+        let original = tab(Role::Original, "first\nsecond");
+        let translation = tab(Role::Translation, "erste\nzweite");
+        let mut frame = frame(0.0, 1.0);
+        assert_eq!(frame_text(&frame, &original, &[]), None);
+
+        frame.tooltip = Some("tooltip".into());
+        assert_eq!(frame_text(&frame, &original, &[]), Some("tooltip"));
+        assert_eq!(frame_text(&frame, &translation, &[]), None);
+
+        frame.line = Some(1);
+        assert_eq!(frame_text(&frame, &original, &[]), Some("second"));
+        assert_eq!(frame_text(&frame, &translation, &[]), Some("zweite"));
+    }
+
+    #[test]
+    fn frames_without_text_are_skipped() {
+        let data = AppData {
+            lyrics: vec![tab(Role::Original, "first")],
+            timings: vec![TimeFrame { line: Some(0), .. frame(0.0, 1.0) }, frame(1.0, 2.0)],
+            path: String::new(),
+            offset_ms: 500,
+            sections: Vec::new(),
+        };
+        let mut out = Vec::new();
+        assert_eq!(write_ass(&mut out, &data).unwrap(), 1);
+
+        let out = String::from_utf8(out).unwrap();
+        let dialogues = out.lines().filter(|l| l.starts_with("Dialogue:")).collect::<Vec<_>>();
+        assert_eq!(dialogues, vec!["Dialogue: 0,0:00:00.50,0:00:01.50,0_en,,0,0,0,,first"]);
+    }
+}
@@ -0,0 +1,236 @@
+
+use failure::Error;
+use imgui::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use configuration::config;
+use constants::{MAX_PATH_LEN, SAVE_FILE_EXT, ASS_FILE_EXT};
+use console::Logger;
+
+/// Named set of file extensions, an empty set matches every file
+pub struct FileFilter {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+}
+
+pub static PROJECT_FILES: FileFilter = FileFilter { name: "Projects", extensions: &[SAVE_FILE_EXT] };
+pub static AUDIO_FILES: FileFilter = FileFilter { name: "Audio", extensions: &["mp3", "wav", "ogg", "flac"] };
+pub static SUBTITLE_FILES: FileFilter = FileFilter { name: "Subtitles", extensions: &[ASS_FILE_EXT] };
+pub static ALL_FILES: FileFilter = FileFilter { name: "All files", extensions: &[] };
+
+impl FileFilter {
+    fn matches(&self, path: &Path) -> bool {
+        self.extensions.is_empty() || path.extension()
+            .and_then(|e| e.to_str())
+            .map_or(false, |e| self.extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum SortBy {
+    Name,
+    Modified,
+    Size
+}
+
+const SORT_KEYS: &[SortBy] = &[SortBy::Name, SortBy::Modified, SortBy::Size];
+
+struct Entry {
+    path: PathBuf,
+    label: ImString,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Directory listing with navigation used by the open and save dialogs.
+/// Directories are always listed before files
+pub struct FileBrowser {
+    logger: Logger,
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    filters: &'static [&'static FileFilter],
+    filter: i32,
+    sort: i32,
+    descending: bool,
+    selected: Option<PathBuf>,
+    new_folder: ImString,
+}
+
+impl FileBrowser {
+    /// Starts in `dir` or in the base directory from settings if it isn't a directory
+    pub fn new<P: AsRef<Path>>(logger: Logger, dir: Option<P>, filters: &'static [&'static FileFilter]) -> Self {
+        let dir = dir.map(|d| d.as_ref().to_path_buf())
+            .filter(|d| d.is_dir())
+            .unwrap_or_else(|| PathBuf::from(&config().dialogs.base_dir));
+
+        let mut browser = FileBrowser {
+            logger,
+            dir: dir.canonicalize().unwrap_or(dir),
+            entries: Vec::new(),
+            filters,
+            filter: 0,
+            sort: 0,
+            descending: false,
+            selected: None,
+            new_folder: ImString::with_capacity(MAX_PATH_LEN),
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// Relative paths typed by the user are relative to the current directory
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    /// Returns the file clicked on this frame
+    pub fn show<'a>(&mut self, ui: &Ui<'a>) -> Option<PathBuf> {
+        self.show_breadcrumbs(ui);
+        self.show_toolbar(ui);
+
+        let mut clicked = None;
+        let size = (config().dialogs.file_browser_width, config().dialogs.file_browser_height);
+        ui.child_frame(im_str!("##entries"), size)
+            .show_borders(true)
+            .show_scrollbar(true)
+            .build(|| {
+                for (idx, entry) in self.entries.iter().enumerate() {
+                    let selected = self.selected.as_ref() == Some(&entry.path);
+                    ui.with_id(idx as i32, || {
+                        if ui.selectable(&entry.label, selected, ImGuiSelectableFlags::empty(), (0.0, 0.0)) {
+                            clicked = Some(idx);
+                        }
+                    });
+                }
+            });
+
+        let entry = clicked.and_then(|idx| self.entries.get(idx)).map(|e| (e.path.clone(), e.is_dir));
+        match entry {
+            Some((path, true)) => {
+                self.navigate(path);
+                None
+            }
+            Some((path, false)) => {
+                self.selected = Some(path.clone());
+                Some(path)
+            }
+            None => None
+        }
+    }
+
+    fn show_breadcrumbs<'a>(&mut self, ui: &Ui<'a>) {
+        let mut target = None;
+        if ui.button(im_str!(".."), (0.0, 0.0)) {
+            target = self.dir.parent().map(Path::to_path_buf);
+        }
+        {
+            let ancestors = self.dir.ancestors().collect::<Vec<_>>();
+            for (idx, dir) in ancestors.iter().rev().enumerate() {
+                let name = dir.file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| dir.to_string_lossy().into_owned());
+                ui.same_line(0.0);
+                ui.with_id(idx as i32, || {
+                    if ui.button(im_str!("{}", name), (0.0, 0.0)) {
+                        target = Some(dir.to_path_buf());
+                    }
+                });
+            }
+        }
+
+        if let Some(dir) = target {
+            self.navigate(dir);
+        }
+    }
+
+    fn show_toolbar<'a>(&mut self, ui: &Ui<'a>) {
+        let filters = self.filters.iter().map(|f| ImString::new(f.name)).collect::<Vec<_>>();
+        let filter_names = filters.iter().map(|f| f.as_ref()).collect::<Vec<&ImStr>>();
+        let sort_names = [im_str!("name"), im_str!("modified"), im_str!("size")];
+
+        let mut changed = false;
+        ui.with_item_width(100.0, || {
+            changed |= ui.combo(im_str!("type"), &mut self.filter, &filter_names, 5);
+            ui.same_line(0.0);
+            changed |= ui.combo(im_str!("sort"), &mut self.sort, &sort_names, 3);
+        });
+        ui.same_line(0.0);
+        changed |= ui.checkbox(im_str!("desc"), &mut self.descending);
+        if changed {
+            self.refresh();
+        }
+
+        ui.with_item_width(150.0, || {
+            ui.input_text(im_str!("##new_folder"), &mut self.new_folder).build();
+        });
+        ui.same_line(0.0);
+        if ui.button(im_str!("new folder"), (0.0, 0.0)) && !self.new_folder.to_str().trim().is_empty() {
+            let dir = self.dir.join(self.new_folder.to_str().trim());
+            match fs::create_dir(&dir) {
+                Ok(_) => {
+                    self.new_folder.clear();
+                    self.navigate(dir);
+                }
+                Err(e) => self.logger.error(format!("Can't create folder: {}", e))
+            }
+        }
+    }
+
+    fn navigate(&mut self, dir: PathBuf) {
+        self.dir = dir;
+        self.selected = None;
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        match self.read_entries() {
+            Ok(entries) => self.entries = entries,
+            Err(e) => {
+                self.logger.error(format!("Can't read {}: {}", self.dir.display(), e));
+                self.entries.clear();
+            }
+        }
+    }
+
+    fn read_entries(&self) -> Result<Vec<Entry>, Error> {
+        let filter = self.filters.get(self.filter as usize).cloned().unwrap_or(&ALL_FILES);
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|e| {
+                let path = e.path();
+                let metadata = fs::metadata(&path).ok()?;
+                let is_dir = metadata.is_dir();
+                if !is_dir && !filter.matches(&path) {
+                    return None;
+                }
+
+                let name = e.file_name().to_string_lossy().into_owned();
+                Some(Entry {
+                    label: if is_dir { ImString::new(format!("{}/", name)) } else { ImString::new(name) },
+                    path,
+                    is_dir,
+                    size: metadata.len(),
+                    modified: metadata.modified().ok(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let sort = SORT_KEYS.get(self.sort as usize).cloned().unwrap_or(SortBy::Name);
+        let descending = self.descending;
+        entries.sort_by(|a, b| {
+            let order = match sort {
+                SortBy::Name => a.label.to_str().to_lowercase().cmp(&b.label.to_str().to_lowercase()),
+                SortBy::Modified => a.modified.cmp(&b.modified),
+                SortBy::Size => a.size.cmp(&b.size)
+            };
+            let order = if descending { order.reverse() } else { order };
+            b.is_dir.cmp(&a.is_dir).then(order)
+        });
+        Ok(entries)
+    }
+}
@@ -0,0 +1,105 @@
+
+use failure::Error;
+use imgui::{FontGlyphRange, ImFontConfig, ImGui};
+
+use std::fs::File;
+use std::io::Read;
+
+use configuration::{config, FontSource};
+use constants::BUILTIN_FONT;
+use console::Logger;
+
+const BUILTIN_FONT_DATA: &[u8] = include_bytes!("../resources/tahoma.ttf");
+
+static GREEK: &[u32] = &[0x0020, 0x00FF, 0x0370, 0x03FF, 0x1F00, 0x1FFF, 0];
+static LATIN_EXTENDED: &[u32] = &[0x0020, 0x024F, 0x1E00, 0x1EFF, 0];
+static ARABIC: &[u32] = &[0x0020, 0x00FF, 0x0600, 0x06FF, 0x0750, 0x077F, 0x08A0, 0x08FF, 0xFB50, 0xFDFF, 0xFE70, 0xFEFF, 0];
+static HEBREW: &[u32] = &[0x0020, 0x00FF, 0x0590, 0x05FF, 0xFB1D, 0xFB4F, 0];
+static ARMENIAN_GEORGIAN: &[u32] = &[0x0020, 0x00FF, 0x0530, 0x058F, 0x10A0, 0x10FF, 0];
+static DEVANAGARI: &[u32] = &[0x0020, 0x00FF, 0x0900, 0x097F, 0];
+
+/// Adds fonts from the `[fonts]` section of settings. The first glyph range
+/// of the first font creates the UI font, everything else is merged into it,
+/// so glyphs missing in a font are taken from the next ones (fallbacks).
+/// Falls back to the bundled font if none of the fonts could be loaded.
+/// Fonts which can't be loaded are logged
+pub fn add_fonts(imgui: &mut ImGui, logger: &mut Logger) {
+    let mut merge = false;
+    for source in &config().fonts.sources {
+        match add_font(imgui, source, merge) {
+            Ok(added) => merge |= added,
+            Err(e) => logger.error(format!("Can't load font {}: {}", source.path, e))
+        }
+    }
+
+    if !merge {
+        font_config(false).add_font(&mut imgui.fonts(), BUILTIN_FONT_DATA, &FontGlyphRange::cyrillic());
+    }
+}
+
+fn add_font(imgui: &mut ImGui, source: &FontSource, merge: bool) -> Result<bool, Error> {
+    let data = load(&source.path)?;
+    let mut merge = merge;
+    for name in &source.glyph_ranges {
+        let range = glyph_range(name)?;
+        font_config(merge).add_font(&mut imgui.fonts(), data, &range);
+        merge = true;
+    }
+    Ok(merge)
+}
+
+fn font_config(merge: bool) -> ImFontConfig {
+    ImFontConfig::new()
+        .oversample_h(1)
+        .pixel_snap_h(true)
+        .size_pixels(config().fonts.size)
+        .rasterizer_multiply(1.0)
+        .merge_mode(merge)
+}
+
+/// Font atlas keeps pointers to font data until it's built, so loaded fonts
+/// live as long as the application does
+fn load(path: &str) -> Result<&'static [u8], Error> {
+    if path == BUILTIN_FONT {
+        return Ok(BUILTIN_FONT_DATA);
+    }
+
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(Box::leak(data.into_boxed_slice()))
+}
+
+/// Named range (`cyrillic`, `japanese`, ...) or a custom one (`0x0600-0x06FF`)
+fn glyph_range(name: &str) -> Result<FontGlyphRange, Error> {
+    Ok(match name {
+        "default" => FontGlyphRange::default(),
+        "cyrillic" => FontGlyphRange::cyrillic(),
+        "chinese" => FontGlyphRange::chinese_full(),
+        "chinese_simplified" => FontGlyphRange::chinese_simplified_common(),
+        "japanese" => FontGlyphRange::japanese(),
+        "korean" => FontGlyphRange::korean(),
+        "thai" => FontGlyphRange::thai(),
+        "greek" => FontGlyphRange::from_slice(GREEK),
+        "latin_extended" => FontGlyphRange::from_slice(LATIN_EXTENDED),
+        "arabic" => FontGlyphRange::from_slice(ARABIC),
+        "hebrew" => FontGlyphRange::from_slice(HEBREW),
+        "armenian_georgian" => FontGlyphRange::from_slice(ARMENIAN_GEORGIAN),
+        "devanagari" => FontGlyphRange::from_slice(DEVANAGARI),
+        custom => {
+            let mut bounds = custom.splitn(2, '-').map(parse_codepoint);
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(from)), Some(Ok(to))) if from > 0 && from <= to => {
+                    let range: &'static [u32] = Box::leak(vec![from, to, 0].into_boxed_slice());
+                    FontGlyphRange::from_slice(range)
+                }
+                _ => bail!("Unknown glyph range '{}'", custom)
+            }
+        }
+    })
+}
+
+fn parse_codepoint(value: &str) -> Result<u32, Error> {
+    let value = value.trim();
+    let hex = value.trim_start_matches("0x").trim_start_matches("U+");
+    Ok(u32::from_str_radix(hex, 16)?)
+}
@@ -0,0 +1,72 @@
+
+use imgui::*;
+
+use constants::MAX_PATH_LEN;
+use state::{TimeFrame, SECTION_KINDS};
+
+/// Edits the label, section, color and notes of a single frame
+pub struct FrameEditor {
+    frame: usize,
+    label: ImString,
+    notes: ImString,
+    section: i32,
+    color: [f32; 3],
+    own_color: bool,
+}
+
+impl FrameEditor {
+    pub fn new(idx: usize, frame: &TimeFrame) -> Self {
+        let mut label = ImString::with_capacity(MAX_PATH_LEN);
+        label.push_str(&frame.label);
+        let mut notes = ImString::with_capacity(MAX_PATH_LEN * 4);
+        notes.push_str(&frame.notes);
+
+        FrameEditor {
+            frame: idx,
+            label,
+            notes,
+            section: SECTION_KINDS.iter().position(|&s| s == frame.section).unwrap_or(0) as i32,
+            color: frame.color(),
+            own_color: frame.color.is_some(),
+        }
+    }
+
+    #[inline]
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr, frame: &mut TimeFrame) -> bool {
+        let mut opened = true;
+        let names = SECTION_KINDS.iter().map(|s| ImString::new(s.name())).collect::<Vec<_>>();
+        let names = names.iter().map(|n| n.as_ref()).collect::<Vec<&ImStr>>();
+
+        ui.window(title)
+            .opened(&mut opened)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                let mut changed = ui.input_text(im_str!("label"), &mut self.label).build();
+                changed |= ui.combo(im_str!("section"), &mut self.section, &names, SECTION_KINDS.len() as i32);
+                changed |= ui.checkbox(im_str!("own color"), &mut self.own_color);
+                if self.own_color {
+                    changed |= ui.color_edit(im_str!("color"), &mut self.color).build();
+                }
+                changed |= ui.input_text(im_str!("notes"), &mut self.notes)
+                    .multiline(ImVec2::new(300.0, 100.0))
+                    .build();
+
+                if changed {
+                    frame.label = self.label.to_str().trim().to_owned();
+                    frame.notes = self.notes.to_str().to_owned();
+                    frame.section = SECTION_KINDS.get(self.section as usize).cloned().unwrap_or_default();
+                    frame.color = if self.own_color { Some(self.color) } else { None };
+                    if !self.own_color {
+                        self.color = frame.color();
+                    }
+                }
+            });
+
+        opened
+    }
+}
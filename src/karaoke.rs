@@ -0,0 +1,178 @@
+
+use imgui::*;
+
+use player::Player;
+use state::{TimeFrame, WordTiming, SectionKind};
+use configuration::config;
+use constants::MAX_PATH_LEN;
+
+/// Separates syllables of a word in the tapping text (`beau|ti|ful`)
+const SYLLABLE_SEPARATOR: char = '|';
+
+/// Records word and syllable timings of a single frame. Every tap marks
+/// the start of the next syllable, the last one lasts until the frame end
+pub struct WordTapper {
+    frame: usize,
+    text: ImString,
+    taps: Vec<f64>,
+    tapping: bool,
+}
+
+impl WordTapper {
+    /// Starts with already tapped words of the frame, or with the frame's lyrics line
+    pub fn new(idx: usize, frame: &TimeFrame, line: Option<&str>) -> Self {
+        let text = if frame.words.is_empty() {
+            line.or(frame.tooltip.as_ref().map(|t| t.as_str()))
+                .unwrap_or("")
+                .to_owned()
+        } else {
+            frame.words.iter()
+                .map(|w| if w.syllables.is_empty() {
+                    w.text.clone()
+                } else {
+                    w.syllables.iter()
+                        .map(|s| s.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join(&SYLLABLE_SEPARATOR.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let mut buf = ImString::with_capacity(MAX_PATH_LEN);
+        buf.push_str(&text);
+        WordTapper {
+            frame: idx,
+            text: buf,
+            taps: Vec::new(),
+            tapping: false,
+        }
+    }
+
+    #[inline]
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr, frame: &mut TimeFrame, player: &mut Player) -> bool {
+        let mut opened = true;
+        ui.window(title)
+            .size(config().main_window.word_tapper_size, ImGuiCond::FirstUseEver)
+            .opened(&mut opened)
+            .collapsible(false)
+            .build(|| {
+                ui.input_text(im_str!("##words"), &mut self.text).build();
+                ui.text(format!("Words: {}, syllables: {}. Split syllables with '{}'",
+                                self.text.to_str().split_whitespace().count(),
+                                self.syllables().len(),
+                                SYLLABLE_SEPARATOR));
+                if self.tapping {
+                    match self.syllables().get(self.taps.len()) {
+                        Some(next) => ui.text(format!("Next: {}", next)),
+                        None => ui.text("Done")
+                    }
+                }
+
+                if ui.button(im_str!("play & tap"), (0.0, 0.0)) {
+                    self.taps.clear();
+                    self.tapping = true;
+                    player.update((&*frame).into());
+                    player.play();
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("tap"), (0.0, 0.0)) {
+                    self.tap(player.position(), frame);
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("clear"), (0.0, 0.0)) {
+                    frame.words.clear();
+                    self.taps.clear();
+                    self.tapping = false;
+                }
+            });
+
+        opened
+    }
+
+    /// Records the start of the next syllable. Words are stored once every
+    /// syllable was tapped. Taps outside of a "play & tap" pass are ignored
+    pub fn tap(&mut self, position: f64, frame: &mut TimeFrame) {
+        if !self.tapping {
+            return;
+        }
+        self.taps.push(position);
+        if self.taps.len() >= self.syllables().len() {
+            frame.words = self.words(frame.end_secs());
+            self.tapping = false;
+            self.taps.clear();
+        }
+    }
+
+    fn syllables(&self) -> Vec<&str> {
+        self.text.to_str()
+            .split_whitespace()
+            .flat_map(|w| w.split(SYLLABLE_SEPARATOR))
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    fn words(&self, frame_end: f64) -> Vec<WordTiming> {
+        let mut taps = self.taps.iter().cloned().peekable();
+        let mut words = Vec::new();
+        for word in self.text.to_str().split_whitespace() {
+            let mut syllables = Vec::new();
+            for syllable in word.split(SYLLABLE_SEPARATOR).filter(|s| !s.is_empty()) {
+                let start = taps.next().unwrap_or(frame_end);
+                let end = taps.peek().cloned().unwrap_or(frame_end);
+                syllables.push(WordTiming {
+                    text: syllable.to_owned(),
+                    start,
+                    end,
+                    syllables: Vec::new(),
+                });
+            }
+            if syllables.is_empty() {
+                continue;
+            }
+
+            words.push(WordTiming {
+                text: syllables.iter().map(|s| s.text.as_str()).collect(),
+                start: syllables[0].start,
+                end: syllables[syllables.len() - 1].end,
+                syllables: if syllables.len() > 1 { syllables } else { Vec::new() },
+            });
+        }
+        words
+    }
+}
+
+/// Shows words of the frame being played, already sung ones are highlighted
+pub fn show_line<'a>(ui: &Ui<'a>, frame: &TimeFrame, position: f64) {
+    if !frame.label.is_empty() || frame.section != SectionKind::None {
+        let [r, g, b] = frame.color();
+        ui.text_colored((r, g, b, 1.0), im_str!("[{}]", frame.title()));
+    }
+    for (idx, word) in frame.words.iter().enumerate() {
+        if idx > 0 {
+            ui.same_line(0.0);
+        }
+        if word.syllables.is_empty() {
+            show_word(ui, word, position);
+        } else {
+            for (idx, syllable) in word.syllables.iter().enumerate() {
+                if idx > 0 {
+                    ui.same_line_spacing(0.0, 0.0);
+                }
+                show_word(ui, syllable, position);
+            }
+        }
+    }
+}
+
+fn show_word<'a>(ui: &Ui<'a>, word: &WordTiming, position: f64) {
+    if word.start <= position {
+        ui.text_colored(config().main_window.karaoke_color, im_str!("{}", word.text));
+    } else {
+        ui.text(&word.text);
+    }
+}
@@ -0,0 +1,211 @@
+
+use failure::Error;
+use glutin::{KeyboardInput, VirtualKeyCode as Key};
+
+use std::fmt;
+
+use configuration::config;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    PlayPause,
+    Stop,
+    AddFrame,
+    NextFrame,
+    PreviousFrame,
+    SeekForward,
+    SeekBackward,
+    Tap,
+    Save,
+    Open,
+    New,
+    ToggleHelp,
+}
+
+/// Actions with their names in settings, descriptions and default bindings
+const ACTIONS: &[(Action, &str, &str, &str)] = &[
+    (Action::PlayPause, "play_pause", "Play / pause", "Ctrl+Space"),
+    (Action::Stop, "stop", "Stop", "Ctrl+Period"),
+    (Action::AddFrame, "add_frame", "Add time frame", "Ctrl+Return"),
+    (Action::NextFrame, "next_frame", "Play next frame", "Ctrl+Down"),
+    (Action::PreviousFrame, "previous_frame", "Play previous frame", "Ctrl+Up"),
+    (Action::SeekForward, "seek_forward", "Seek forward", "Ctrl+Right"),
+    (Action::SeekBackward, "seek_backward", "Seek backward", "Ctrl+Left"),
+    (Action::Tap, "tap", "Tap next word", "F5"),
+    (Action::Save, "save", "Save project", "Ctrl+S"),
+    (Action::Open, "open", "Open project", "Ctrl+O"),
+    (Action::New, "new", "New project", "Ctrl+N"),
+    (Action::ToggleHelp, "toggle_help", "Show / hide shortcuts", "F1"),
+];
+
+const KEYS: &[(&str, Key)] = &[
+    ("A", Key::A), ("B", Key::B), ("C", Key::C), ("D", Key::D), ("E", Key::E), ("F", Key::F),
+    ("G", Key::G), ("H", Key::H), ("I", Key::I), ("J", Key::J), ("K", Key::K), ("L", Key::L),
+    ("M", Key::M), ("N", Key::N), ("O", Key::O), ("P", Key::P), ("Q", Key::Q), ("R", Key::R),
+    ("S", Key::S), ("T", Key::T), ("U", Key::U), ("V", Key::V), ("W", Key::W), ("X", Key::X),
+    ("Y", Key::Y), ("Z", Key::Z),
+    ("0", Key::Key0), ("1", Key::Key1), ("2", Key::Key2), ("3", Key::Key3), ("4", Key::Key4),
+    ("5", Key::Key5), ("6", Key::Key6), ("7", Key::Key7), ("8", Key::Key8), ("9", Key::Key9),
+    ("F1", Key::F1), ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4), ("F5", Key::F5),
+    ("F6", Key::F6), ("F7", Key::F7), ("F8", Key::F8), ("F9", Key::F9), ("F10", Key::F10),
+    ("F11", Key::F11), ("F12", Key::F12),
+    ("Space", Key::Space), ("Return", Key::Return), ("Escape", Key::Escape), ("Tab", Key::Tab),
+    ("Back", Key::Back), ("Insert", Key::Insert), ("Delete", Key::Delete),
+    ("Home", Key::Home), ("End", Key::End), ("PageUp", Key::PageUp), ("PageDown", Key::PageDown),
+    ("Left", Key::Left), ("Right", Key::Right), ("Up", Key::Up), ("Down", Key::Down),
+    ("Comma", Key::Comma), ("Period", Key::Period), ("Minus", Key::Minus), ("Equals", Key::Equals),
+    ("LBracket", Key::LBracket), ("RBracket", Key::RBracket),
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct KeyChord {
+    key: Key,
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl KeyChord {
+    pub fn from_input(input: &KeyboardInput) -> Option<Self> {
+        input.virtual_keycode.map(|key| KeyChord {
+            key,
+            ctrl: input.modifiers.ctrl,
+            shift: input.modifiers.shift,
+            alt: input.modifiers.alt,
+        })
+    }
+
+    /// Parses chords like `Ctrl+Shift+S` or `F1`
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let mut chord = KeyChord { key: Key::Escape, ctrl: false, shift: false, alt: false };
+        let mut key = None;
+        for part in value.split('+').map(|p| p.trim()) {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                _ => {
+                    ensure!(key.is_none(), "Key binding '{}' has more than one key", value);
+                    key = KEYS.iter()
+                        .find(|&&(name, _)| name.eq_ignore_ascii_case(part))
+                        .map(|&(_, k)| k);
+                    ensure!(key.is_some(), "Unknown key '{}' in key binding '{}'", part, value);
+                }
+            }
+        }
+        chord.key = key.ok_or(format_err!("Key binding '{}' has no key", value))?;
+        Ok(chord)
+    }
+
+    /// Chords handled by text fields: characters and navigation without modifiers,
+    /// word navigation, clipboard and undo with Ctrl. They would both edit
+    /// the text and run the shortcut
+    pub fn is_text_editing(&self) -> bool {
+        let function_key = match self.key {
+            Key::F1 | Key::F2 | Key::F3 | Key::F4 | Key::F5 | Key::F6 |
+            Key::F7 | Key::F8 | Key::F9 | Key::F10 | Key::F11 | Key::F12 => true,
+            _ => false
+        };
+        let ctrl_editing = match self.key {
+            Key::Left | Key::Right | Key::Up | Key::Down | Key::Home | Key::End |
+            Key::Back | Key::Delete | Key::Return |
+            Key::A | Key::C | Key::V | Key::X | Key::Y | Key::Z => true,
+            _ => false
+        };
+        if self.alt || function_key {
+            false
+        } else {
+            !self.ctrl || ctrl_editing
+        }
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        match KEYS.iter().find(|&&(_, k)| k == self.key) {
+            Some(&(name, _)) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.key)
+        }
+    }
+}
+
+/// Name of the action in settings
+fn name(action: Action) -> &'static str {
+    ACTIONS.iter()
+        .find(|&&(a, _, _, _)| a == action)
+        .map(|&(_, n, _, _)| n)
+        .unwrap_or("")
+}
+
+pub struct Keymap {
+    bindings: Vec<(KeyChord, Action)>,
+}
+
+impl Keymap {
+    /// Default bindings overridden by the `[keybindings]` section of settings.
+    /// Invalid entries are skipped and reported, a chord bound to several
+    /// actions runs only the first of them
+    pub fn from_config() -> (Self, Vec<Error>) {
+        let config = config();
+        let mut errors = Vec::new();
+        for name in config.keybindings.keys() {
+            if !ACTIONS.iter().any(|&(_, n, _, _)| n == name) {
+                errors.push(format_err!("Unknown action '{}' in key bindings", name));
+            }
+        }
+
+        let mut bindings: Vec<(KeyChord, Action)> = ACTIONS.iter()
+            .filter_map(|&(action, name, _, default)| {
+                let chord = config.keybindings.get(name).map(|c| c.as_str()).unwrap_or(default);
+                match KeyChord::parse(chord) {
+                    Ok(chord) => Some((chord, action)),
+                    Err(e) => {
+                        errors.push(e);
+                        KeyChord::parse(default).ok().map(|chord| (chord, action))
+                    }
+                }
+            })
+            .collect();
+
+        let mut idx = 0;
+        while idx < bindings.len() {
+            let (chord, action) = bindings[idx];
+            match bindings[..idx].iter().find(|&&(c, _)| c == chord) {
+                Some(&(_, first)) => {
+                    errors.push(format_err!("Key binding '{}' of '{}' is already used by '{}'",
+                                            chord, name(action), name(first)));
+                    bindings.remove(idx);
+                }
+                None => idx += 1
+            }
+        }
+
+        (Keymap { bindings }, errors)
+    }
+
+    pub fn action(&self, chord: &KeyChord) -> Option<Action> {
+        self.bindings.iter().find(|&&(c, _)| c == *chord).map(|&(_, a)| a)
+    }
+
+    /// Descriptions of the actions with their key chords
+    pub fn describe(&self) -> Vec<(&'static str, String)> {
+        self.bindings.iter()
+            .map(|&(chord, action)| {
+                let description = ACTIONS.iter()
+                    .find(|&&(a, _, _, _)| a == action)
+                    .map(|&(_, _, d, _)| d)
+                    .unwrap_or("");
+                (description, chord.to_string())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,169 @@
+
+use failure::Error;
+
+/// Languages written right-to-left
+const RTL_LANGUAGES: &[&str] = &["ar", "arc", "ckb", "dv", "fa", "he", "iw", "ku", "ps", "sd", "ug", "ur", "yi"];
+/// Scripts written right-to-left
+const RTL_SCRIPTS: &[&str] = &["Adlm", "Arab", "Hebr", "Nkoo", "Rohg", "Syrc", "Thaa"];
+
+const DISPLAY_NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("be", "Belarusian"),
+    ("cs", "Czech"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("ur", "Urdu"),
+    ("zh", "Chinese"),
+];
+
+/// Validates a BCP-47 language tag (`language[-script][-region][-variant]*[-extension]*[-x-private]`)
+/// and returns it with the conventional casing: `sr-Latn-RS`, `zh-Hant-TW`
+pub fn canonical_tag(tag: &str) -> Result<String, Error> {
+    let subtags = tag.trim().split(|c| c == '-' || c == '_').collect::<Vec<_>>();
+    ensure!(subtags.iter().all(|s| !s.is_empty() && s.len() <= 8 && s.chars().all(|c| c.is_ascii_alphanumeric())),
+            "'{}' is not a valid language tag", tag);
+
+    let mut result = Vec::with_capacity(subtags.len());
+    let mut subtags = subtags.into_iter().peekable();
+
+    let language = subtags.next().unwrap_or("");
+    if language.eq_ignore_ascii_case("x") {
+        ensure!(subtags.peek().is_some(), "'{}' has an empty private use part", tag);
+        return Ok(tag.trim().to_ascii_lowercase().replace('_', "-"));
+    }
+    ensure!(is_alpha(language) && (language.len() <= 3 || language.len() >= 5) && language.len() >= 2,
+            "'{}' has invalid primary language '{}'", tag, language);
+    result.push(language.to_ascii_lowercase());
+
+    // Extended language subtags
+    let mut extlangs = 0;
+    while let Some(&s) = subtags.peek() {
+        if language.len() <= 3 && s.len() == 3 && is_alpha(s) && extlangs < 3 {
+            result.push(s.to_ascii_lowercase());
+            extlangs += 1;
+            subtags.next();
+        } else {
+            break;
+        }
+    }
+
+    if let Some(&s) = subtags.peek() {
+        if s.len() == 4 && is_alpha(s) {
+            result.push(title_case(s));
+            subtags.next();
+        }
+    }
+
+    if let Some(&s) = subtags.peek() {
+        if (s.len() == 2 && is_alpha(s)) || (s.len() == 3 && is_digit(s)) {
+            result.push(s.to_ascii_uppercase());
+            subtags.next();
+        }
+    }
+
+    while let Some(&s) = subtags.peek() {
+        if (s.len() >= 5) || (s.len() == 4 && s.chars().next().map_or(false, |c| c.is_ascii_digit())) {
+            result.push(s.to_ascii_lowercase());
+            subtags.next();
+        } else {
+            break;
+        }
+    }
+
+    while let Some(s) = subtags.next() {
+        ensure!(s.len() == 1, "'{}' has unexpected subtag '{}'", tag, s);
+        let private = s.eq_ignore_ascii_case("x");
+        let mut count = 0;
+        result.push(s.to_ascii_lowercase());
+        while let Some(&s) = subtags.peek() {
+            if !private && s.len() == 1 {
+                break;
+            }
+            ensure!(private || s.len() >= 2, "'{}' has invalid extension subtag '{}'", tag, s);
+            result.push(s.to_ascii_lowercase());
+            count += 1;
+            subtags.next();
+        }
+        ensure!(count > 0, "'{}' has an empty extension '{}'", tag, s);
+    }
+
+    Ok(result.join("-"))
+}
+
+/// Whether the language (or script, if the tag has one) is written right-to-left
+pub fn is_rtl(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or("");
+    match subtags.find(|s| s.len() == 4 && is_alpha(s)) {
+        Some(script) => RTL_SCRIPTS.iter().any(|s| s.eq_ignore_ascii_case(script)),
+        None => RTL_LANGUAGES.iter().any(|l| l.eq_ignore_ascii_case(language))
+    }
+}
+
+/// Human readable name of the tag's primary language, the tag itself if unknown
+pub fn display_name(tag: &str) -> String {
+    let language = tag.split('-').next().unwrap_or("");
+    DISPLAY_NAMES.iter()
+        .find(|&&(code, _)| code.eq_ignore_ascii_case(language))
+        .map(|&(_, name)| name.to_owned())
+        .unwrap_or_else(|| tag.to_owned())
+}
+
+/// Reorders a logical right-to-left line for left-to-right only rendering:
+/// runs of right-to-left characters are mirrored, left-to-right runs (numbers,
+/// latin words) keep their order. Only the order changes, Arabic letters
+/// aren't shaped into their joined forms, so they are shown isolated
+pub fn visual_order(line: &str) -> String {
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    for c in line.chars() {
+        let rtl = is_rtl_char(c) || (!c.is_alphanumeric() && runs.last().map_or(true, |r| r.0));
+        match runs.last_mut() {
+            Some(ref mut run) if run.0 == rtl => run.1.push(c),
+            _ => runs.push((rtl, c.to_string()))
+        }
+    }
+
+    runs.into_iter()
+        .rev()
+        .map(|(rtl, run)| if rtl { run.chars().rev().collect() } else { run })
+        .collect()
+}
+
+fn is_rtl_char(c: char) -> bool {
+    match c as u32 {
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF => true,
+        _ => false
+    }
+}
+
+#[inline]
+fn is_alpha(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+#[inline]
+fn is_digit(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn title_case(s: &str) -> String {
+    s.chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+        .collect()
+}
@@ -10,8 +10,8 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
-extern crate ignore;
 extern crate config;
+extern crate toml;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -26,34 +26,80 @@ mod console;
 mod song;
 mod constants;
 mod configuration;
+mod analysis;
+mod karaoke;
+mod export;
+mod parallel_editor;
+mod language;
+mod fonts;
+mod keybindings;
+mod preferences;
+mod session;
+mod clipboard;
+mod file_browser;
+mod errors;
+mod calibration;
+mod timing;
+mod frame_editor;
+mod section_editor;
+mod repetitions;
 
 use imgui::*;
 
+use std::path::{Path, PathBuf};
+
 use support_gfx::AppContext;
 use main_window::MainWindow;
-use dialogs::{OpenFileDialog, OpenFileState};
+use dialogs::{OpenFileDialog, OpenFileState, ErrorDialog, read_state_from_file};
 use console::{Console, Logger};
+use keybindings::{Action, KeyChord, Keymap};
+use configuration::{self, SettingsWatcher};
+use preferences::Preferences;
+use calibration::Calibration;
+use session::Session;
+use state::AppData;
+use clipboard::Clipboard;
 
 pub struct Program {
     logger: Logger,
+    keymap: Keymap,
+    help_enabled: bool,
     console_enabled: bool,
+    settings_watcher: SettingsWatcher,
+    session: Session,
+    preferences: Option<Preferences>,
+    calibration: Option<Calibration>,
     open_file_dialog: Option<OpenFileDialog>,
-    main_window: Option<MainWindow>,
-    console: Option<Console>
+    clipboard: Clipboard,
+    windows: Vec<MainWindow>,
+    next_window_id: usize,
+    /// Window which receives shortcuts, the last focused one
+    active: Option<usize>,
+    /// Window which outputs audio
+    playing: Option<usize>,
+    console: Option<Console>,
+    error_dialog: ErrorDialog,
 }
 
 impl AppContext for Program {
     fn show<'a>(&mut self, ui: &Ui<'a>) -> bool {
+        self.logger.flush();
+        self.watch_settings();
+
         let mut opened = true;
         ui.main_menu_bar(|| {
             ui.menu(im_str!("File"))
                 .build(|| {
                     if ui.menu_item(im_str!("New")).build() {
-                        self.main_window = Some(MainWindow::new(self.logger.clone()));
+                        self.new_project();
                     }
                     if ui.menu_item(im_str!("Open")).build() {
                         self.open_file_dialog = Some(OpenFileDialog::new(self.logger.clone()));
                     }
+                    self.show_recent_menu(ui);
+                    if ui.menu_item(im_str!("Preferences")).build() {
+                        self.preferences = Some(Preferences::new(self.logger.clone()));
+                    }
                     if ui.menu_item(im_str!("Exit")).build() {
                         opened = false;
                     }
@@ -63,22 +109,28 @@ impl AppContext for Program {
                     if ui.menu_item(im_str!("Console")).selected(&mut self.console_enabled).build() {
                         self.console = Some(Console::new(self.logger.clone()));
                     }
+                    ui.menu_item(im_str!("Shortcuts")).selected(&mut self.help_enabled).build();
+                    if ui.menu_item(im_str!("Latency calibration")).build() {
+                        self.calibration = Some(Calibration::new(self.logger.clone()));
+                    }
                 });
         });
 
         if let Some(mut ofd) = self.open_file_dialog.take() {
             match ofd.show(ui) {
-                OpenFileState::Opened(data) => self.main_window = Some(MainWindow::load(self.logger.clone(), data)),
+                OpenFileState::Opened(data, path) => self.open_project(data, path),
                 OpenFileState::Displaying => self.open_file_dialog = Some(ofd),
                 OpenFileState::Closed => {}
             }
         }
 
-        if let Some(mut window) = self.main_window.take() {
-            if window.show(ui) {
-                self.main_window = Some(window);
-            }
-        }
+        let closed = self.windows.iter_mut()
+            .filter_map(|w| if w.show(ui) { None } else { Some(w.id()) })
+            .collect::<Vec<_>>();
+        self.windows.retain(|w| !closed.contains(&w.id()));
+        self.update_active();
+        self.solo_playback();
+        self.sync_project();
 
         if let Some(mut console) = self.console.take() {
             if console.show(ui) && self.console_enabled {
@@ -86,24 +138,231 @@ impl AppContext for Program {
             }
         }
 
+        if let Some(mut preferences) = self.preferences.take() {
+            if preferences.show(ui) {
+                self.preferences = Some(preferences);
+            }
+        }
+
+        if let Some(mut calibration) = self.calibration.take() {
+            if calibration.show(ui) {
+                self.calibration = Some(calibration);
+            }
+        }
+
+        if self.help_enabled {
+            self.show_help(ui);
+        }
+
+        self.error_dialog.show(ui);
+
         opened
     }
+
+    fn key_pressed(&mut self, key: KeyChord, text_input: bool) {
+        if text_input && key.is_text_editing() {
+            return;
+        }
+
+        match self.keymap.action(&key) {
+            Some(Action::New) => self.new_project(),
+            Some(Action::Open) => self.open_file_dialog = Some(OpenFileDialog::new(self.logger.clone())),
+            Some(Action::ToggleHelp) => self.help_enabled = !self.help_enabled,
+            Some(action) => {
+                let active = self.active;
+                if let Some(window) = self.windows.iter_mut().find(|w| Some(w.id()) == active) {
+                    window.handle_action(action);
+                }
+            }
+            None => {}
+        }
+    }
 }
 
 impl Program {
-    fn new(logger: Logger) -> Self {
-        Program {
-            console: Some(Console::new(logger.clone())),
+    fn new(mut logger: Logger) -> Self {
+        let console = Console::new(logger.clone());
+        let error_dialog = ErrorDialog::new(logger.clone());
+        let (keymap, errors) = Keymap::from_config();
+        for e in errors {
+            logger.warn(format!("{}", e));
+        }
+
+        let mut program = Program {
+            console: Some(console),
+            error_dialog,
             logger,
+            keymap,
+            help_enabled: false,
             console_enabled: true,
+            settings_watcher: SettingsWatcher::new(),
+            session: Session::load(),
+            preferences: None,
+            calibration: None,
             open_file_dialog: None,
-            main_window: None,
+            clipboard: Clipboard::new(),
+            windows: Vec::new(),
+            next_window_id: 0,
+            active: None,
+            playing: None,
+        };
+        program.watch_settings();
+        if let Some(project) = program.session.last_project().map(Path::to_path_buf) {
+            program.open_recent(project);
+        }
+        program
+    }
+
+    fn show_recent_menu<'a>(&mut self, ui: &Ui<'a>) {
+        let mut selected = None;
+        let mut clear = false;
+        {
+            let recent = self.session.recent();
+            ui.menu(im_str!("Open Recent"))
+                .enabled(!recent.is_empty())
+                .build(|| {
+                    for project in recent {
+                        if ui.menu_item(im_str!("{}", project.display())).build() {
+                            selected = Some(project.clone());
+                        }
+                    }
+                    ui.separator();
+                    clear = ui.menu_item(im_str!("Clear")).build();
+                });
+        }
+
+        if let Some(project) = selected {
+            self.open_recent(project);
+        }
+        if clear {
+            self.session.clear_recent();
+            self.save_session();
+        }
+    }
+
+    fn open_recent(&mut self, project: PathBuf) {
+        match read_state_from_file(&project) {
+            Ok(data) => self.open_project(data, project),
+            Err(e) => {
+                self.logger.report(&e);
+                self.session.remove(&project);
+                self.save_session();
+            }
         }
     }
+
+    fn new_project(&mut self) {
+        configuration::set_project(None::<&Path>);
+        self.watch_settings();
+        let id = self.next_window_id();
+        let window = MainWindow::new(id, self.logger.clone(), self.clipboard.clone());
+        self.add_window(window);
+    }
+
+    /// Project settings are applied before the window is created, so it uses them
+    fn open_project(&mut self, data: AppData, path: PathBuf) {
+        configuration::set_project(Some(&path));
+        self.watch_settings();
+        let id = self.next_window_id();
+        let window = MainWindow::load(id, self.logger.clone(), self.clipboard.clone(), data, path);
+        self.add_window(window);
+    }
+
+    fn next_window_id(&mut self) -> usize {
+        self.next_window_id += 1;
+        self.next_window_id
+    }
+
+    fn add_window(&mut self, window: MainWindow) {
+        self.active = Some(window.id());
+        self.windows.push(window);
+    }
+
+    /// Focus changes only when a single window reports it, a new window and
+    /// the previously focused one may both report it on the first frame
+    fn update_active(&mut self) {
+        let focused = self.windows.iter().filter(|w| w.is_focused()).map(|w| w.id()).collect::<Vec<_>>();
+        if focused.len() == 1 {
+            self.active = focused.first().cloned();
+        }
+        if !self.windows.iter().any(|w| Some(w.id()) == self.active) {
+            self.active = self.windows.last().map(|w| w.id());
+        }
+    }
+
+    /// Only one project outputs audio: once a project starts playing
+    /// the one which played before is paused
+    fn solo_playback(&mut self) {
+        let playing = self.playing;
+        let started = self.windows.iter()
+            .find(|w| w.is_playing() && Some(w.id()) != playing)
+            .map(|w| w.id());
+
+        if let Some(id) = started {
+            for window in self.windows.iter().filter(|w| w.id() != id) {
+                window.pause();
+            }
+            self.playing = Some(id);
+        }
+    }
+
+    /// Remembers the active project (it changes when a new project is saved
+    /// or another window gets focus) and applies its settings
+    fn sync_project(&mut self) {
+        let active = self.active;
+        let project = self.windows.iter()
+            .find(|w| Some(w.id()) == active)
+            .and_then(|w| w.project())
+            .map(Path::to_path_buf);
+        if self.session.set_project(project.as_ref().map(|p| p.as_path())) {
+            configuration::set_project(project);
+            self.save_session();
+        }
+    }
+
+    fn save_session(&mut self) {
+        if let Err(e) = self.session.save() {
+            self.logger.warn(format!("Can't save session: {}", e));
+        }
+    }
+
+    /// Reloads settings and key bindings when settings files change
+    fn watch_settings(&mut self) {
+        match self.settings_watcher.poll() {
+            Some(Ok(_)) => self.logger.info("Settings loaded"),
+            Some(Err(e)) => self.logger.warn(format!("Can't load settings, using defaults: {}", e)),
+            None => return
+        }
+        self.logger.debug(format!("Settings files: {:?}", configuration::settings_files()));
+
+        let (keymap, errors) = Keymap::from_config();
+        for e in errors {
+            self.logger.warn(format!("{}", e));
+        }
+        self.keymap = keymap;
+    }
+
+    fn show_help<'a>(&mut self, ui: &Ui<'a>) {
+        let keymap = &self.keymap;
+        ui.window(im_str!("Keyboard shortcuts"))
+            .opened(&mut self.help_enabled)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                ui.columns(2, im_str!("##shortcuts"), false);
+                for (description, chord) in keymap.describe() {
+                    ui.text(description);
+                    ui.next_column();
+                    ui.text(chord);
+                    ui.next_column();
+                }
+                ui.columns(1, im_str!("##shortcuts_end"), false);
+            });
+    }
 }
 
 fn main() {
     let logger = Logger::new();
-    support_gfx::run("melos", Program::new(logger));
+    support_gfx::run("melos", logger.clone(), Program::new(logger));
 }
 
@@ -1,24 +1,65 @@
 
 use imgui::*;
 
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+
 use support_gfx::AppContext;
 use player::Player;
-use dialogs::SaveFileDialog;
-use state::{TimeFrame, ImLanguageTab, AppData};
-use configuration::CONFIG;
+use dialogs::{SaveFileDialog, SongFileDialog, SongFileState, FileFormat};
+use state::{self, TimeFrame, ImLanguageTab, AppData, SubtitleStyle, Position, Role, Section};
+use configuration::config;
 use constants::MAX_PATH_LEN;
 use console::Logger;
+use clipboard::{Clipboard, ClipboardData};
+use analysis::{self, AlignMsg, RepeatMsg};
+use karaoke::{self, WordTapper};
+use parallel_editor::ParallelEditor;
+use frame_editor::FrameEditor;
+use section_editor::SectionEditor;
+use repetitions::RepetitionList;
+use language;
+use timing;
+use keybindings::Action;
+use song::TimeSpan;
 
 pub struct MainWindow {
+    id: usize,
     logger: Logger,
+    clipboard: Clipboard,
+    focused: bool,
     lyrics: Vec<ImLanguageTab>,
     timings: Vec<TimeFrame>,
+    sections: Vec<Section>,
     path: ImString,
+    project: Option<PathBuf>,
     player: Player,
     save_file_dialog: Option<SaveFileDialog>,
+    song_file_dialog: Option<SongFileDialog>,
     tooltip_input: ImString,
     language: usize,
-    lang_name_buf: ImString
+    lang_name_buf: ImString,
+    /// Language tag being edited, applied on Enter
+    tag_buf: ImString,
+    /// Tab and tag the buffer was filled from, it's refilled when they change
+    tag_source: Option<(usize, String)>,
+    /// Why the edited language tag can't be applied
+    tag_error: Option<String>,
+    align_event: Option<Receiver<AlignMsg>>,
+    repeat_event: Option<Receiver<RepeatMsg>>,
+    repetitions: Option<RepetitionList>,
+    word_tapper: Option<WordTapper>,
+    parallel_editor: Option<ParallelEditor>,
+    frame_editor: Option<FrameEditor>,
+    section_editor: Option<SectionEditor>,
+    section_name_buf: ImString,
+    /// Frame which is dragged in the frame list
+    dragging: Option<usize>,
+    current_frame: usize,
+    /// Project offset applied on export, in milliseconds
+    offset_ms: i32,
+    shift_ms: i32,
+    scale_factor: f32
 }
 
 impl AppContext for MainWindow {
@@ -28,31 +69,82 @@ impl AppContext for MainWindow {
 }
 
 impl MainWindow {
-    pub fn new(logger: Logger) -> Self {
+    pub fn new(id: usize, logger: Logger, clipboard: Clipboard) -> Self {
         MainWindow {
+            id,
+            clipboard,
+            focused: false,
             player: Player::new(logger.clone()),
             lyrics: vec![ImLanguageTab::default()],
             timings: Vec::new(),
+            sections: Vec::new(),
             path: ImString::with_capacity(MAX_PATH_LEN),
-            tooltip_input: ImString::with_capacity(CONFIG.main_window.tooltip_len),
-            lang_name_buf: ImString::with_capacity(CONFIG.main_window.lang_name_len),
+            project: None,
+            tooltip_input: ImString::with_capacity(config().main_window.tooltip_len),
+            lang_name_buf: ImString::with_capacity(config().main_window.lang_name_len),
+            tag_buf: ImString::with_capacity(config().main_window.lang_name_len),
+            tag_source: None,
+            tag_error: None,
             save_file_dialog: None,
+            song_file_dialog: None,
             language: 0,
+            align_event: None,
+            repeat_event: None,
+            repetitions: None,
+            word_tapper: None,
+            parallel_editor: None,
+            frame_editor: None,
+            section_editor: None,
+            section_name_buf: ImString::with_capacity(MAX_PATH_LEN),
+            dragging: None,
+            current_frame: 0,
+            offset_ms: 0,
+            shift_ms: 0,
+            scale_factor: 1.0,
             logger,
         }
     }
 
-    pub fn load(logger: Logger, data: AppData) -> Self {
+    pub fn load(id: usize, logger: Logger, clipboard: Clipboard, data: AppData, project: PathBuf) -> Self {
         let mut player = Player::new(logger.clone());
         player.open(&data.path);
+        let mut lyrics: Vec<ImLanguageTab> = data.lyrics.into_iter().map(|t| t.into()).collect();
+        if lyrics.is_empty() {
+            lyrics.push(ImLanguageTab::default());
+        }
+        if lyrics.iter().all(|t| t.role != Role::Original) {
+            lyrics[0].role = Role::Original;
+        }
         MainWindow {
-            lyrics: data.lyrics.into_iter().map(|t| t.into()).collect(),
+            id,
+            clipboard,
+            focused: false,
+            lyrics,
             timings: data.timings.into_iter().collect(),
+            sections: data.sections,
             path: ImString::new(data.path),
-            tooltip_input: ImString::with_capacity(CONFIG.main_window.tooltip_len),
-            lang_name_buf: ImString::with_capacity(CONFIG.main_window.lang_name_len),
+            project: Some(project),
+            tooltip_input: ImString::with_capacity(config().main_window.tooltip_len),
+            lang_name_buf: ImString::with_capacity(config().main_window.lang_name_len),
+            tag_buf: ImString::with_capacity(config().main_window.lang_name_len),
+            tag_source: None,
+            tag_error: None,
             save_file_dialog: None,
+            song_file_dialog: None,
             language: 0,
+            align_event: None,
+            repeat_event: None,
+            repetitions: None,
+            word_tapper: None,
+            parallel_editor: None,
+            frame_editor: None,
+            section_editor: None,
+            section_name_buf: ImString::with_capacity(MAX_PATH_LEN),
+            dragging: None,
+            current_frame: 0,
+            offset_ms: data.offset_ms,
+            shift_ms: 0,
+            scale_factor: 1.0,
             logger,
             player,
         }
@@ -60,59 +152,171 @@ impl MainWindow {
 
     fn show_main_window<'a>(&mut self, ui: &Ui<'a>) -> bool {
         let mut opened = true;
-        ui.window(im_str!("Lyrics"))
-            .size(CONFIG.main_window.main_window_size, ImGuiCond::FirstUseEver)
+        let title = self.window_title("Lyrics");
+        ui.window(&title)
+            .size(config().main_window.main_window_size, ImGuiCond::FirstUseEver)
             .opened(&mut opened)
             .collapsible(false)
             .menu_bar(true)
             .build(|| {
+                self.focused = ui.is_root_window_focused();
                 self.show_menu(ui);
                 ui.columns(2, im_str!("##container"), false);
-                ui.input_text(im_str!(""), &mut self.lyrics[self.language].text)
-                    .multiline(ImVec2::new(
-                            CONFIG.main_window.lyrics_input_width,
-                            CONFIG.main_window.lyrics_input_height))
-                    .build();
+                self.show_lyrics(ui);
                 ui.next_column();
                 let column_idx = ui.get_column_index();
-                ui.set_column_offset(column_idx, CONFIG.main_window.column_offset);
-                ui.with_item_width(CONFIG.main_window.song_path_input_len, || {
+                ui.set_column_offset(column_idx, config().main_window.column_offset);
+                ui.with_item_width(config().main_window.song_path_input_len, || {
                     ui.input_text(im_str!("##song"), &mut self.path).build();
                 });
                 ui.same_line(0.0);
                 if ui.button(im_str!("open"), (0.0, 0.0)) {
                     self.player.open(self.path.to_str());
                 }
-                ui.with_item_width(CONFIG.main_window.timeframe_tooltip_width, || {
+                ui.same_line(0.0);
+                if ui.button(im_str!("..."), (0.0, 0.0)) {
+                    self.song_file_dialog = Some(SongFileDialog::new(self.logger.clone(), self.path.to_str()));
+                }
+                ui.with_item_width(config().main_window.timeframe_tooltip_width, || {
                     ui.input_text(im_str!("##tooltip"), &mut self.tooltip_input).build();
                 });
                 ui.same_line(0.0);
                 if ui.button(im_str!("+"), (0.0, 0.0)) {
-                    {
-                        let tooltip = self.tooltip_input.to_str();
-                        if tooltip.is_empty() {
-                            self.timings.push(TimeFrame::new());
-                        } else {
-                            self.timings.push(TimeFrame::with_tooltip(tooltip));
-                        }
-                    }
-                    self.tooltip_input.clear();
+                    self.add_frame();
                 }
                 self.show_quatrains(ui);
                 ui.spacing();
                 self.player.show(ui);
+                self.show_karaoke(ui);
                 self.show_save_file_dialog(ui);
             });
 
+        self.show_song_file_dialog(ui);
+
+        self.show_word_tapper(ui);
+        self.show_frame_editor(ui);
+        self.show_section_editor(ui);
+        self.show_repetitions(ui);
+        self.show_parallel_editor(ui);
+        if self.timings.iter().any(|x| x.remove) {
+            self.frames_changed();
+        }
         self.timings.retain(|x| !x.remove);
+        self.apply_alignment();
+        self.apply_repetitions();
 
         opened
     }
 
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Path of the project file, `None` until a new project is saved
+    pub fn project(&self) -> Option<&Path> {
+        self.project.as_ref().map(|p| p.as_path())
+    }
+
+    /// Whether the window had focus on the last frame
+    #[inline]
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.player.is_playing()
+    }
+
+    #[inline]
+    pub fn pause(&self) {
+        self.player.pause();
+    }
+
+    /// File name of the project
+    fn name(&self) -> String {
+        self.project.as_ref()
+            .and_then(|p| p.file_stem())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".into())
+    }
+
+    /// Windows of different projects must have different ids, so the project
+    /// name is shown in the title and the window id is appended after `###`
+    fn window_title(&self, title: &str) -> ImString {
+        ImString::new(format!("{} - {}###{}{}", title, self.name(), title, self.id))
+    }
+
+    pub fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::PlayPause => self.player.toggle(),
+            Action::Stop => self.player.stop(),
+            Action::AddFrame => self.add_frame(),
+            Action::NextFrame => {
+                let next = self.current_frame + 1;
+                self.play_frame(next);
+            }
+            Action::PreviousFrame => {
+                let previous = self.current_frame.saturating_sub(1);
+                self.play_frame(previous);
+            }
+            Action::SeekForward => self.player.seek(config().player.seek_step as i64),
+            Action::SeekBackward => self.player.seek(-(config().player.seek_step as i64)),
+            Action::Tap => {
+                if let Some(ref mut tapper) = self.word_tapper {
+                    if let Some(frame) = self.timings.get_mut(tapper.frame()) {
+                        tapper.tap(self.player.position(), frame);
+                    }
+                }
+            }
+            Action::Save => self.save_file_dialog = Some(SaveFileDialog::new(self.logger.clone())),
+            Action::Open | Action::New | Action::ToggleHelp => {}
+        }
+    }
+
+    fn add_frame(&mut self) {
+        {
+            let tooltip = self.tooltip_input.to_str();
+            if tooltip.is_empty() {
+                self.timings.push(TimeFrame::new());
+            } else {
+                self.timings.push(TimeFrame::with_tooltip(tooltip));
+            }
+        }
+        self.tooltip_input.clear();
+    }
+
+    fn play_frame(&mut self, idx: usize) {
+        if let Some(span) = self.timings.get(idx).map(TimeSpan::from) {
+            self.current_frame = idx;
+            self.player.update(span);
+            self.player.play();
+        }
+    }
+
     fn show_save_file_dialog<'a>(&mut self, ui: &Ui<'a>) {
         if let Some(mut sfd) = self.save_file_dialog.take() {
-            if sfd.show(ui, || self.to_app_data()) {
+            let title = self.window_title(sfd.title());
+            if sfd.show(ui, &title, || self.to_app_data()) {
                 self.save_file_dialog = Some(sfd);
+            } else if let Some(project) = sfd.saved_project() {
+                self.project = Some(project.to_path_buf());
+            }
+        }
+    }
+
+    fn show_song_file_dialog<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut sfd) = self.song_file_dialog.take() {
+            let title = self.window_title("Open Song");
+            match sfd.show(ui, &title) {
+                SongFileState::Selected(path) => {
+                    self.path.clear();
+                    self.path.push_str(&path.to_string_lossy());
+                    self.player.open(&path);
+                }
+                SongFileState::Displaying => self.song_file_dialog = Some(sfd),
+                SongFileState::Closed => {}
             }
         }
     }
@@ -121,7 +325,9 @@ impl MainWindow {
         AppData {
             lyrics: self.lyrics.iter().map(|t| t.into()).collect(),
             timings: self.timings.iter().cloned().collect(),
-            path: self.path.to_str().to_owned()
+            path: self.path.to_str().to_owned(),
+            offset_ms: self.offset_ms,
+            sections: self.sections.clone()
         }
     }
 
@@ -131,29 +337,76 @@ impl MainWindow {
                 if ui.menu_item(im_str!("Save")).build() {
                     self.save_file_dialog = Some(SaveFileDialog::new(self.logger.clone()));
                 }
+                if ui.menu_item(im_str!("Export ASS")).build() {
+                    self.save_file_dialog = Some(SaveFileDialog::with_format(self.logger.clone(), FileFormat::Ass));
+                }
             });
+            ui.menu(im_str!("Edit")).build(|| self.show_edit_menu(ui));
+            ui.menu(im_str!("Frames")).build(|| self.show_frames_menu(ui));
+            ui.menu(im_str!("Sections")).build(|| self.show_sections_menu(ui));
             ui.menu(im_str!("Languages")).build(|| {
                 let mut lang_id = self.language;
                 for (idx, tab) in self.lyrics.iter().enumerate() {
                     ui.with_id(idx as i32, || {
-                        if ui.menu_item(&tab.lang).build() {
+                        if ui.menu_item(im_str!("{} ({})", tab.name, tab.lang))
+                            .selected(&mut (idx == lang_id))
+                            .build()
+                        {
                             lang_id = idx;
                         }
                     });
                 }
                 self.language = lang_id;
                 ui.menu(im_str!("New")).build(|| {
-                    ui.with_item_width(CONFIG.main_window.new_lang_input_width, || {
+                    ui.with_item_width(config().main_window.new_lang_input_width, || {
                         ui.input_text(im_str!("##new_lang"), &mut self.lang_name_buf)
                             .build();
                     });
                     ui.same_line(0.0);
                     if ui.button(im_str!("+"), (0.0, 0.0)) {
-                        let tab = ImLanguageTab::new(self.lang_name_buf.to_str(), "");
-                        self.lyrics.push(tab);
-                        self.lang_name_buf.clear();
+                        match language::canonical_tag(self.lang_name_buf.to_str()) {
+                            Ok(ref tag) if self.has_lang(tag) =>
+                                self.logger.error(format!("Language {} already has a tab", tag)),
+                            Ok(tag) => {
+                                self.lyrics.push(ImLanguageTab::new(tag, ""));
+                                self.lang_name_buf.clear();
+                            }
+                            Err(e) => self.logger.error(format!("{}", e))
+                        }
                     }
                 });
+                ui.menu(im_str!("Properties")).build(|| self.show_language_menu(ui));
+                ui.menu(im_str!("Style")).build(|| self.show_style_menu(ui));
+            });
+            ui.menu(im_str!("View")).build(|| {
+                let mut parallel = self.parallel_editor.is_some();
+                if ui.menu_item(im_str!("Side by side")).selected(&mut parallel).build() {
+                    self.parallel_editor = if parallel {
+                        Some(ParallelEditor::new(self.lyrics.len()))
+                    } else {
+                        None
+                    };
+                }
+            });
+            ui.menu(im_str!("Tools")).build(|| {
+                if ui.menu_item(im_str!("Align lyrics"))
+                    .enabled(self.align_event.is_none())
+                    .build()
+                {
+                    self.start_alignment();
+                }
+                if ui.menu_item(im_str!("Find repetitions"))
+                    .enabled(self.repeat_event.is_none())
+                    .build()
+                {
+                    match self.player.track() {
+                        Some(track) => {
+                            self.repeat_event = Some(analysis::find_repetitions(track));
+                            self.logger.info("Looking for repeated parts of the song...");
+                        }
+                        None => self.logger.warn("The song isn't loaded yet")
+                    }
+                }
             });
             if ui.button(im_str!("X"), (0.0, 0.0)) {
                 self.lyrics.remove(self.language);
@@ -165,18 +418,380 @@ impl MainWindow {
         });
     }
 
+    /// Copies frames or the current language tab to the clipboard shared
+    /// by all open projects
+    fn show_edit_menu<'a>(&mut self, ui: &Ui<'a>) {
+        if ui.menu_item(im_str!("Copy current frame"))
+            .enabled(self.current_frame < self.timings.len())
+            .build()
+        {
+            let frame = self.timings[self.current_frame].clone();
+            self.clipboard.copy(ClipboardData::Frames(self.id, vec![frame]));
+        }
+        if ui.menu_item(im_str!("Copy all frames"))
+            .enabled(!self.timings.is_empty())
+            .build()
+        {
+            self.clipboard.copy(ClipboardData::Frames(self.id, self.timings.clone()));
+        }
+        if ui.menu_item(im_str!("Copy language")).build() {
+            let tab = (&self.lyrics[self.language]).into();
+            self.clipboard.copy(ClipboardData::Language(tab));
+        }
+        if ui.menu_item(im_str!("Paste")).enabled(!self.clipboard.is_empty()).build() {
+            self.paste();
+        }
+        ui.separator();
+        ui.with_item_width(100.0, || {
+            ui.input_int(im_str!("export offset (ms)"), &mut self.offset_ms).build();
+        });
+    }
+
+    /// Operations apply to the selected frames, to all frames if none is selected
+    fn show_frames_menu<'a>(&mut self, ui: &Ui<'a>) {
+        let selected = self.timings.iter().filter(|f| f.selected).count();
+        if ui.menu_item(im_str!("Select all")).build() {
+            for frame in &mut self.timings {
+                frame.selected = true;
+            }
+        }
+        if ui.menu_item(im_str!("Select none")).enabled(selected > 0).build() {
+            for frame in &mut self.timings {
+                frame.selected = false;
+            }
+        }
+        ui.separator();
+        ui.menu(im_str!("Shift")).enabled(!self.timings.is_empty()).build(|| {
+            ui.with_item_width(100.0, || {
+                ui.input_int(im_str!("ms"), &mut self.shift_ms).build();
+            });
+            ui.same_line(0.0);
+            if ui.button(im_str!("shift"), (0.0, 0.0)) && self.shift_ms != 0 {
+                let shifted = timing::shift(&mut self.timings, self.shift_ms as f64 / 1000.0);
+                let shifted_ms = (shifted * 1000.0).round() as i32;
+                let count = timing::targets(&self.timings).len();
+                if shifted_ms != self.shift_ms {
+                    self.logger.warn(format!("{} frames were shifted by {} ms only, the first one starts with the song",
+                                             count, shifted_ms));
+                } else {
+                    self.logger.info(format!("{} frames were shifted by {} ms", count, shifted_ms));
+                }
+            }
+        });
+        ui.menu(im_str!("Scale")).enabled(!self.timings.is_empty()).build(|| {
+            ui.with_item_width(100.0, || {
+                ui.input_float(im_str!("factor"), &mut self.scale_factor).decimal_precision(4).build();
+            });
+            ui.same_line(0.0);
+            if ui.button(im_str!("scale"), (0.0, 0.0)) && self.scale_factor > 0.0 {
+                timing::scale(&mut self.timings, self.scale_factor as f64);
+            }
+        });
+        if ui.menu_item(im_str!("Split at playhead")).enabled(!self.timings.is_empty()).build() {
+            if !timing::split(&mut self.timings, self.player.position()) {
+                self.logger.warn("No frame under the playhead");
+            }
+            self.frames_changed();
+        }
+        if ui.menu_item(im_str!("Merge selected")).enabled(selected > 1).build() {
+            match timing::merge(&mut self.timings) {
+                Ok(_) => self.frames_changed(),
+                Err(e) => self.logger.warn(e)
+            }
+        }
+        if ui.menu_item(im_str!("Sort by start")).build() {
+            timing::sort(&mut self.timings);
+            self.frames_changed();
+        }
+        if ui.menu_item(im_str!("Close gaps")).build() {
+            timing::close_gaps(&mut self.timings);
+            self.frames_changed();
+        }
+    }
+
+    /// Shared sections like a chorus: their text is edited once and
+    /// used by every linked frame
+    fn show_sections_menu<'a>(&mut self, ui: &Ui<'a>) {
+        let selected = self.timings.iter().filter(|f| f.selected).count();
+        let mut edit = None;
+        let mut insert = None;
+        let mut link = None;
+        let mut delete = None;
+        for (idx, section) in self.sections.iter().enumerate() {
+            ui.with_id(idx as i32, || {
+                ui.menu(im_str!("{}", section.name)).build(|| {
+                    if ui.menu_item(im_str!("Edit")).build() {
+                        edit = Some(idx);
+                    }
+                    if ui.menu_item(im_str!("Insert at playhead")).build() {
+                        insert = Some(idx);
+                    }
+                    if ui.menu_item(im_str!("Link selected frames")).enabled(selected > 0).build() {
+                        link = Some(idx);
+                    }
+                    if ui.menu_item(im_str!("Delete")).build() {
+                        delete = Some(idx);
+                    }
+                });
+            });
+        }
+        if ui.menu_item(im_str!("Unlink selected frames")).enabled(selected > 0).build() {
+            for frame in self.timings.iter_mut().filter(|f| f.selected) {
+                frame.shared = None;
+            }
+        }
+        ui.menu(im_str!("New")).build(|| {
+            ui.with_item_width(config().main_window.new_lang_input_width, || {
+                ui.input_text(im_str!("##new_section"), &mut self.section_name_buf).build();
+            });
+            ui.same_line(0.0);
+            if ui.button(im_str!("+"), (0.0, 0.0)) {
+                let name = self.section_name_buf.to_str().trim().to_owned();
+                if name.is_empty() || self.sections.iter().any(|s| s.name == name) {
+                    self.logger.error(format!("Section name '{}' is empty or already used", name));
+                } else {
+                    self.sections.push(Section::new(name));
+                    self.section_editor = Some(SectionEditor::new(self.sections.len() - 1, self.sections.last().unwrap()));
+                    self.section_name_buf.clear();
+                }
+            }
+        });
+
+        if let Some(idx) = edit {
+            self.section_editor = Some(SectionEditor::new(idx, &self.sections[idx]));
+        }
+        if let Some(idx) = insert {
+            self.insert_occurrence(idx);
+        }
+        if let Some(idx) = link {
+            let name = self.sections[idx].name.clone();
+            for frame in self.timings.iter_mut().filter(|f| f.selected) {
+                frame.shared = Some(name.clone());
+            }
+        }
+        if let Some(idx) = delete {
+            let section = self.sections.remove(idx);
+            for frame in self.timings.iter_mut().filter(|f| f.shared.as_ref() == Some(&section.name)) {
+                frame.shared = None;
+            }
+            self.section_editor = None;
+        }
+    }
+
+    /// New frame linked to the section at the playhead, as long as its
+    /// first occurrence or `new_occurrence_len` seconds if there is none
+    fn insert_occurrence(&mut self, idx: usize) {
+        let name = self.sections[idx].name.clone();
+        let length = self.timings.iter()
+            .find(|f| f.shared.as_ref() == Some(&name))
+            .map(|f| f.end_secs() - f.start_secs())
+            .unwrap_or(config().main_window.new_occurrence_len);
+        let start = self.player.position();
+        self.timings.push(TimeFrame {
+            start: state::from_secs(start),
+            end: state::from_secs(start + length),
+            shared: Some(name),
+            .. Default::default()
+        });
+        timing::sort(&mut self.timings);
+        self.frames_changed();
+    }
+
+    /// Windows editing a frame by its index are closed when frames move
+    fn frames_changed(&mut self) {
+        self.word_tapper = None;
+        self.frame_editor = None;
+    }
+
+    fn paste(&mut self) {
+        match self.clipboard.paste() {
+            Some(ClipboardData::Frames(source, frames)) => {
+                // lines of another project are unrelated, sections are kept if the names match
+                let other = source != self.id;
+                let sections = &self.sections;
+                self.timings.extend(frames.into_iter().map(|mut frame| {
+                    frame.selected = false;
+                    if other {
+                        frame.line = None;
+                        if !frame.shared.as_ref().map_or(false, |name| sections.iter().any(|s| s.name == *name)) {
+                            frame.shared = None;
+                        }
+                    }
+                    frame
+                }));
+                timing::sort(&mut self.timings);
+                self.frames_changed();
+            }
+            Some(ClipboardData::Language(tab)) => {
+                let mut tab: ImLanguageTab = tab.into();
+                if self.has_lang(tab.lang.to_str()) {
+                    return self.logger.error(format!("Language {} already has a tab", tab.lang));
+                }
+                if self.lyrics.iter().any(|t| t.role == Role::Original) {
+                    tab.role = Role::Translation;
+                }
+                self.lyrics.push(tab);
+                self.language = self.lyrics.len() - 1;
+            }
+            None => {}
+        }
+    }
+
+    /// Lyrics of the current language. Right-to-left text is edited in logical
+    /// order and additionally shown right aligned in display order
+    fn show_lyrics<'a>(&mut self, ui: &Ui<'a>) {
+        let tab = &mut self.lyrics[self.language];
+        let height = if tab.rtl {
+            config().main_window.lyrics_input_height / 2.0
+        } else {
+            config().main_window.lyrics_input_height
+        };
+        ui.input_text(im_str!(""), &mut tab.text)
+            .multiline(ImVec2::new(config().main_window.lyrics_input_width, height))
+            .build();
+
+        if tab.rtl {
+            ui.child_frame(im_str!("##rtl_preview"), (config().main_window.lyrics_input_width, height))
+                .show_borders(true)
+                .show_scrollbar(true)
+                .build(|| {
+                    for line in tab.text.to_str().lines() {
+                        let line = im_str!("{}", language::visual_order(line));
+                        let width = ui.calc_text_size(line, false, -1.0).x;
+                        ui.text("");
+                        ui.same_line((config().main_window.lyrics_input_width - width - 20.0).max(0.0));
+                        ui.text(line.to_str());
+                    }
+                });
+        }
+    }
+
+    /// Tag, name, role and direction of the current language tab
+    fn show_language_menu<'a>(&mut self, ui: &Ui<'a>) {
+        let idx = self.language;
+        let mut original = self.lyrics[idx].role == Role::Original;
+        let source = (idx, self.lyrics[idx].lang.to_str().to_owned());
+        if self.tag_source.as_ref() != Some(&source) {
+            self.tag_buf.clear();
+            self.tag_buf.push_str(&source.1);
+            self.tag_source = Some(source);
+            self.tag_error = None;
+        }
+        if ui.input_text(im_str!("tag"), &mut self.tag_buf).enter_returns_true(true).build() {
+            self.change_tag(idx);
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Press Enter to apply the tag");
+        }
+        if let Some(ref error) = self.tag_error {
+            ui.same_line(0.0);
+            ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("{}", error));
+        } else if !self.lyrics[idx].is_valid() {
+            ui.same_line(0.0);
+            ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("invalid BCP-47 tag"));
+        }
+        {
+            let tab = &mut self.lyrics[idx];
+            ui.input_text(im_str!("name"), &mut tab.name).build();
+            ui.checkbox(im_str!("right-to-left"), &mut tab.rtl);
+        }
+        if ui.checkbox(im_str!("original"), &mut original) && original {
+            for (i, tab) in self.lyrics.iter_mut().enumerate() {
+                tab.role = if i == idx { Role::Original } else { Role::Translation };
+            }
+        }
+    }
+
+    #[inline]
+    fn has_lang(&self, lang: &str) -> bool {
+        self.lyrics.iter().any(|t| t.lang.to_str() == lang)
+    }
+
+    /// Applies the edited tag. Section texts are stored by tag, so tags must
+    /// stay unique and not empty. A rejected tag stays in the buffer with
+    /// the error, an accepted one takes the section texts along
+    fn change_tag(&mut self, idx: usize) {
+        let tag = self.tag_buf.to_str().trim().to_owned();
+        let duplicate = self.lyrics.iter().enumerate().any(|(i, t)| i != idx && t.lang.to_str() == tag);
+        self.tag_error = if tag.is_empty() {
+            Some("the tag is empty".into())
+        } else if duplicate {
+            Some(format!("{} already has a tab", tag))
+        } else {
+            None
+        };
+        if self.tag_error.is_some() {
+            return;
+        }
+
+        let old = self.lyrics[idx].lang.to_str().to_owned();
+        for section in &mut self.sections {
+            section.rename_lang(&old, &tag);
+        }
+        let lang = &mut self.lyrics[idx].lang;
+        lang.clear();
+        lang.push_str(&tag);
+    }
+
+    /// Subtitles style of the current language tab
+    fn show_style_menu<'a>(&mut self, ui: &Ui<'a>) {
+        let idx = self.language;
+        let role = self.lyrics[idx].role;
+        let mut style = self.lyrics[idx].style.clone().unwrap_or_else(|| SubtitleStyle::for_role(role));
+        let mut font = ImString::with_capacity(MAX_PATH_LEN);
+        font.push_str(&style.font);
+        let mut size = style.size as i32;
+        let mut top = style.position == Position::Top;
+
+        let mut changed = ui.input_text(im_str!("font"), &mut font).build();
+        changed |= ui.input_int(im_str!("size"), &mut size).build();
+        changed |= ui.color_edit(im_str!("color"), &mut style.color).build();
+        changed |= ui.checkbox(im_str!("on top"), &mut top);
+
+        if changed {
+            style.font = font.to_str().to_owned();
+            style.size = size.max(1) as u32;
+            style.position = if top { Position::Top } else { Position::Bottom };
+            self.lyrics[idx].style = Some(style);
+        }
+    }
+
     fn show_quatrains<'a>(&mut self, ui: &Ui<'a>) {
-        ui.child_frame(im_str!("quatrains"), CONFIG.main_window.quatrains_frame_size)
+        ui.child_frame(im_str!("quatrains"), config().main_window.quatrains_frame_size)
             .show_scrollbar(true)
             .show_borders(true)
             .build(|| {
                 let mut play = None;
+                let mut tap = None;
+                let mut edit = None;
+                let mut dragged = None;
+                let mut row_tops = Vec::with_capacity(self.timings.len());
+                let lyrics = &self.lyrics[self.language];
+                let sections = &self.sections;
                 for (idx, frame) in self.timings.iter_mut().enumerate() {
+                    row_tops.push(ui.get_cursor_screen_pos().1);
                     ui.with_id(idx as i32, || {
+                        ui.checkbox(im_str!("##selected"), &mut frame.selected);
+                        ui.same_line(0.0);
+                        ui.button(im_str!("="), (0.0, 0.0));
+                        if ui.is_item_active() {
+                            dragged = Some(idx);
+                        }
+                        ui.same_line(0.0);
                         if ui.button(im_str!("X"), (0.0, 0.0)) {
                             frame.remove = true;
                         }
                         ui.same_line(0.0);
+                        let [r, g, b] = frame.color();
+                        ui.with_color_var(ImGuiCol::Button, (r * 0.6, g * 0.6, b * 0.6, 1.0), || {
+                            if ui.button(im_str!("{}##label", frame.title()), (80.0, 0.0)) {
+                                edit = Some(idx);
+                            }
+                        });
+                        if ui.is_item_hovered() && !frame.notes.is_empty() {
+                            ui.tooltip_text(&frame.notes);
+                        }
+                        ui.same_line(0.0);
                         let mut time_range = [frame.start, frame.end];
                         ui.input_float2(im_str!(""), &mut time_range)
                             .decimal_precision(2)
@@ -184,20 +799,184 @@ impl MainWindow {
                         if ui.is_item_hovered() {
                             if let Some(ref t) = frame.tooltip {
                                 ui.tooltip_text(t);
+                            } else if let Some(line) = frame.text(lyrics.lang.to_str(), lyrics.text.to_str(), sections) {
+                                ui.tooltip_text(line);
                             }
                         }
                         frame.start = time_range[0];
                         frame.end = time_range[1];
                         ui.same_line(0.0);
                         if ui.button(im_str!("play"), (0.0, 0.0)) {
-                            play = Some(frame.into());
+                            play = Some(idx);
+                        }
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("words"), (0.0, 0.0)) {
+                            tap = Some(idx);
                         }
                     });
                 }
-                play.map(|span| {
-                    self.player.update(span);
-                    self.player.play();
-                });
+                if let Some(idx) = tap {
+                    let frame = &self.timings[idx];
+                    let line = frame.text(lyrics.lang.to_str(), lyrics.text.to_str(), sections);
+                    self.word_tapper = Some(WordTapper::new(idx, frame, line));
+                }
+                if let Some(idx) = edit {
+                    self.frame_editor = Some(FrameEditor::new(idx, &self.timings[idx]));
+                }
+                self.drag_frame(dragged, &row_tops, ui.imgui().mouse_pos().1);
+                if let Some(idx) = play {
+                    self.play_frame(idx);
+                }
             });
     }
+
+    /// The frame is moved to the row under the mouse once the handle is released
+    fn drag_frame(&mut self, dragged: Option<usize>, row_tops: &[f32], mouse_y: f32) {
+        match (self.dragging, dragged) {
+            (_, Some(idx)) => self.dragging = Some(idx),
+            (Some(from), None) => {
+                self.dragging = None;
+                let to = row_tops.iter().rposition(|&top| top <= mouse_y).unwrap_or(0);
+                if from != to && from < self.timings.len() {
+                    let frame = self.timings.remove(from);
+                    self.timings.insert(to, frame);
+                    self.frames_changed();
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn show_frame_editor<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut editor) = self.frame_editor.take() {
+            let title = self.window_title("Frame");
+            let opened = match self.timings.get_mut(editor.frame()) {
+                Some(frame) => editor.show(ui, &title, frame),
+                None => false
+            };
+            if opened {
+                self.frame_editor = Some(editor);
+            }
+        }
+    }
+
+    fn show_section_editor<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut editor) = self.section_editor.take() {
+            let title = self.window_title("Section");
+            if editor.show(ui, &title, &mut self.sections, &self.lyrics, &mut self.timings) {
+                self.section_editor = Some(editor);
+            }
+        }
+    }
+
+    fn show_repetitions<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut list) = self.repetitions.take() {
+            let title = self.window_title("Repetitions");
+            let count = self.timings.len();
+            if list.show(ui, &title, &mut self.timings, &mut self.player) {
+                self.repetitions = Some(list);
+            }
+            if self.timings.len() != count {
+                self.frames_changed();
+            }
+        }
+    }
+
+    fn show_word_tapper<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut tapper) = self.word_tapper.take() {
+            let title = self.window_title("Word timings");
+            let opened = match self.timings.get_mut(tapper.frame()) {
+                Some(frame) => tapper.show(ui, &title, frame, &mut self.player),
+                None => false
+            };
+            if opened {
+                self.word_tapper = Some(tapper);
+            }
+        }
+    }
+
+    fn show_parallel_editor<'a>(&mut self, ui: &Ui<'a>) {
+        if let Some(mut editor) = self.parallel_editor.take() {
+            let title = self.window_title("Side by side");
+            if editor.show(ui, &title, &mut self.lyrics, &self.timings) {
+                self.parallel_editor = Some(editor);
+            }
+        }
+    }
+
+    fn show_karaoke<'a>(&self, ui: &Ui<'a>) {
+        let position = self.player.position();
+        let frame = self.timings.iter()
+            .find(|f| !f.words.is_empty() && f.start_secs() <= position && position < f.end_secs());
+        if let Some(frame) = frame {
+            karaoke::show_line(ui, frame, position);
+        }
+    }
+
+    fn start_alignment(&mut self) {
+        let lines = self.lyrics[self.language].text.to_str()
+            .lines()
+            .enumerate()
+            .filter(|&(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| (idx, line.to_owned()))
+            .collect();
+
+        self.align_event = Some(analysis::align(self.path.to_str(), lines));
+        self.logger.info("Aligning lyrics with the song...");
+    }
+
+    /// Adds draft frames for aligned lines without a frame once the alignment
+    /// is done. Existing frames may be refined already, so they are kept
+    fn apply_alignment(&mut self) {
+        let msg = match self.align_event {
+            Some(ref e) => e.try_recv().ok(),
+            None => None
+        };
+
+        match msg {
+            Some(AlignMsg::Done(timings)) => {
+                let drafts = timings.iter()
+                    .filter(|t| !self.timings.iter().any(|f| f.line == Some(t.line)))
+                    .map(|t| TimeFrame::for_line(t.line, t.start, t.end))
+                    .collect::<Vec<_>>();
+                let kept = timings.len() - drafts.len();
+                if !drafts.is_empty() {
+                    self.logger.info(format!("{} draft time frames were created", drafts.len()));
+                    self.timings.extend(drafts);
+                    timing::sort(&mut self.timings);
+                    self.frames_changed();
+                }
+                if kept > 0 {
+                    self.logger.info(format!("{} aligned lines already have frames, they were kept", kept));
+                }
+                self.align_event = None;
+            }
+            Some(AlignMsg::Failed(e)) => {
+                self.logger.report(&e);
+                self.align_event = None;
+            }
+            None => {}
+        }
+    }
+
+    /// Opens the list of repeated parts once the search is done
+    fn apply_repetitions(&mut self) {
+        let msg = match self.repeat_event {
+            Some(ref e) => e.try_recv().ok(),
+            None => None
+        };
+
+        match msg {
+            Some(RepeatMsg::Done(repetitions)) => {
+                self.logger.info(format!("{} repeated parts were found", repetitions.len()));
+                self.repetitions = Some(RepetitionList::new(repetitions));
+                self.repeat_event = None;
+            }
+            Some(RepeatMsg::Failed(e)) => {
+                self.logger.report(&e);
+                self.repeat_event = None;
+            }
+            None => {}
+        }
+    }
 }
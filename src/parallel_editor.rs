@@ -0,0 +1,132 @@
+
+use imgui::*;
+
+use state::{ImLanguageTab, TimeFrame};
+use configuration::config;
+use constants::{MIN_PARALLEL_LANGUAGES, MAX_PARALLEL_LANGUAGES};
+
+/// Edits several language tabs side by side. All columns live in a single
+/// scrolling region, so scrolling is always synchronized, and every row holds
+/// the same line (by index or by the linked time frame) in each language
+pub struct ParallelEditor {
+    languages: Vec<i32>,
+    by_frame: bool,
+}
+
+impl ParallelEditor {
+    pub fn new(tabs: usize) -> Self {
+        ParallelEditor {
+            languages: (0..MIN_PARALLEL_LANGUAGES).map(|i| i.min(tabs.saturating_sub(1)) as i32).collect(),
+            by_frame: false,
+        }
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr, lyrics: &mut Vec<ImLanguageTab>, timings: &[TimeFrame]) -> bool {
+        for lang in &mut self.languages {
+            if *lang as usize >= lyrics.len() {
+                *lang = 0;
+            }
+        }
+
+        let mut opened = true;
+        ui.window(title)
+            .size(config().main_window.parallel_editor_size, ImGuiCond::FirstUseEver)
+            .opened(&mut opened)
+            .collapsible(false)
+            .build(|| {
+                self.show_header(ui, lyrics);
+                ui.separator();
+                ui.child_frame(im_str!("##parallel_lines"), (0.0, 0.0))
+                    .show_scrollbar(true)
+                    .build(|| {
+                        if self.by_frame {
+                            self.show_by_frame(ui, lyrics, timings);
+                        } else {
+                            self.show_by_index(ui, lyrics);
+                        }
+                    });
+            });
+
+        opened
+    }
+
+    fn show_header<'a>(&mut self, ui: &Ui<'a>, lyrics: &[ImLanguageTab]) {
+        let names = lyrics.iter().map(|t| t.lang.as_ref()).collect::<Vec<&ImStr>>();
+        let offset = if self.by_frame { 1 } else { 0 };
+
+        ui.columns(self.languages.len() as i32 + offset, im_str!("##parallel_header"), false);
+        if self.by_frame {
+            ui.text("frame");
+            ui.next_column();
+        }
+        for (idx, lang) in self.languages.iter_mut().enumerate() {
+            ui.with_id(idx as i32, || {
+                ui.with_item_width(-1.0, || {
+                    ui.combo(im_str!("##lang"), lang, &names, 5);
+                });
+            });
+            ui.next_column();
+        }
+        ui.columns(1, im_str!("##parallel_header_end"), false);
+
+        if ui.button(im_str!("+"), (0.0, 0.0)) && self.languages.len() < MAX_PARALLEL_LANGUAGES {
+            let next = self.languages.len().min(lyrics.len().saturating_sub(1));
+            self.languages.push(next as i32);
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("-"), (0.0, 0.0)) && self.languages.len() > MIN_PARALLEL_LANGUAGES {
+            self.languages.pop();
+        }
+        ui.same_line(0.0);
+        ui.checkbox(im_str!("align by frame"), &mut self.by_frame);
+    }
+
+    fn show_by_index<'a>(&self, ui: &Ui<'a>, lyrics: &mut Vec<ImLanguageTab>) {
+        let rows = self.languages.iter()
+            .map(|&l| lyrics[l as usize].text.to_str().lines().count())
+            .max()
+            .unwrap_or(0);
+
+        ui.columns(self.languages.len() as i32, im_str!("##parallel_columns"), true);
+        // One extra row to append new lines
+        for row in 0..rows + 1 {
+            for (col, &lang) in self.languages.iter().enumerate() {
+                show_cell(ui, &mut lyrics[lang as usize], row, col);
+                ui.next_column();
+            }
+        }
+        ui.columns(1, im_str!("##parallel_columns_end"), false);
+    }
+
+    fn show_by_frame<'a>(&self, ui: &Ui<'a>, lyrics: &mut Vec<ImLanguageTab>, timings: &[TimeFrame]) {
+        ui.columns(self.languages.len() as i32 + 1, im_str!("##parallel_frames"), true);
+        for frame in timings {
+            let row = match frame.line {
+                Some(line) => line,
+                None => continue
+            };
+
+            ui.text(format!("{:.2} - {:.2}", frame.start, frame.end));
+            ui.next_column();
+            for (col, &lang) in self.languages.iter().enumerate() {
+                show_cell(ui, &mut lyrics[lang as usize], row, col);
+                ui.next_column();
+            }
+        }
+        ui.columns(1, im_str!("##parallel_frames_end"), false);
+    }
+}
+
+fn show_cell<'a>(ui: &Ui<'a>, tab: &mut ImLanguageTab, row: usize, col: usize) {
+    let mut buf = ImString::with_capacity(config().main_window.parallel_line_len);
+    buf.push_str(tab.line(row).unwrap_or(""));
+
+    let id = (row * MAX_PARALLEL_LANGUAGES + col) as i32;
+    ui.with_id(id, || {
+        ui.with_item_width(-1.0, || {
+            if ui.input_text(im_str!("##line"), &mut buf).build() {
+                tab.set_line(row, buf.to_str());
+            }
+        });
+    });
+}
@@ -1,12 +1,11 @@
 
 use std::path::Path;
-use std::sync::mpsc::Receiver;
 
 use imgui::*;
 
 use support_gfx::AppContext;
-use song::{Song, SongMsg, TimeSpan};
-use configuration::CONFIG;
+use song::{Song, TimeSpan, Track, output_devices};
+use configuration::{self, config};
 use console::Logger;
 
 pub struct Player {
@@ -14,24 +13,31 @@ pub struct Player {
     song: Song,
     time_span: TimeSpan,
     volume: f32,
-    loaded_event: Option<Receiver<SongMsg>>
+    looping: bool,
+    /// Names of the output devices, the first one is the default device
+    devices: Vec<ImString>,
+    device: i32,
 }
 
 impl Player {
     #[inline]
     pub fn new(logger: Logger) -> Self {
-        Player {
+        let mut player = Player {
+            song: Song::new(logger.clone()),
             logger,
-            song: Song::new(),
-            volume: CONFIG.player.default_volume,
+            volume: config().player.default_volume,
             time_span: TimeSpan::default(),
-            loaded_event: None,
-        }
+            looping: false,
+            devices: Vec::new(),
+            device: 0,
+        };
+        player.refresh_devices();
+        player
     }
 
     #[inline]
     pub fn open<P: AsRef<Path>>(&mut self, path: P) {
-        self.loaded_event = Some(self.song.open(path));
+        self.song.open(path);
     }
 
     #[inline]
@@ -44,6 +50,29 @@ impl Player {
         self.song.play(self.time_span);
     }
 
+    #[inline]
+    pub fn toggle(&mut self) {
+        if self.song.is_playing() {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Moves the cursor by `delta` seconds and keeps playing until the end
+    /// of the current time span
+    pub fn seek(&mut self, delta: i64) {
+        let end = self.time_span.start + self.time_span.duration;
+        let start = (self.position() as i64 + delta).max(0) as u32;
+        self.time_span = TimeSpan::new(start, end.saturating_sub(start).max(1));
+        self.song.seek(self.time_span);
+    }
+
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.song.is_playing()
+    }
+
     #[inline]
     pub fn stop(&self) {
         self.song.stop();
@@ -54,11 +83,56 @@ impl Player {
         self.song.pause();
     }
 
+    /// Position of the playback cursor from the song start in seconds,
+    /// precise to a sample and compensated for the output latency
+    #[inline]
+    pub fn position(&self) -> f64 {
+        self.song.position()
+    }
+
+    /// Decoded song, `None` while it's decoding or if it can't be decoded
+    #[inline]
+    pub fn track(&self) -> Option<Track> {
+        self.song.track()
+    }
+
     #[inline]
     pub fn update_volume(&mut self) {
         self.song.volume(self.volume / 100.0);
     }
 
+    /// The device from settings stays in the list even if it's disconnected
+    fn refresh_devices(&mut self) {
+        let mut names = output_devices();
+        let selected = config().player.output_device.clone();
+        if let Some(ref selected) = selected {
+            if !names.contains(selected) {
+                names.push(selected.clone());
+            }
+        }
+
+        self.device = selected
+            .and_then(|s| names.iter().position(|n| *n == s))
+            .map_or(0, |idx| idx as i32 + 1);
+        self.devices = Some("default".to_owned()).into_iter()
+            .chain(names)
+            .map(ImString::new)
+            .collect();
+    }
+
+    /// Remembers the device in settings, songs of all windows move to it
+    fn select_device(&mut self) {
+        let device = match self.device {
+            0 => None,
+            idx => self.devices.get(idx as usize).map(|d| d.to_str().to_owned())
+        };
+
+        match configuration::update_user(|settings| settings.player.output_device = device) {
+            Ok(_) => self.song.reconnect(),
+            Err(e) => self.logger.error(format!("Can't save output device: {}", e))
+        }
+    }
+
     #[inline]
     fn start(&mut self) -> f32 {
         to_f(self.time_span.start)
@@ -71,21 +145,8 @@ impl Player {
 
     #[inline]
     fn progress(&self) -> f32 {
-        let start = self.time_span.start;
-        let begin = self.song.progress().checked_sub(start).unwrap_or(start);
-        begin as f32 / self.time_span.duration as f32
-    }
-
-    #[inline]
-    fn log_load_status(&mut self) {
-        if let Some(ref e) = self.loaded_event {
-            if let Ok(msg) = e.try_recv() {
-                match msg {
-                    SongMsg::Loaded => self.logger.log("Song was loaded"),
-                    SongMsg::Failed(e) => self.logger.log(format!("{}", e))
-                }
-            }
-        }
+        let offset = self.position() - self.time_span.start as f64;
+        (offset / self.time_span.duration.max(1) as f64).max(0.0).min(1.0) as f32
     }
 }
 
@@ -95,14 +156,26 @@ fn to_f(time: u32) -> f32 {
     minutes as f32 + seconds
 }
 
+/// `1:05.25` for 65.25 seconds
+pub fn format_time(time: f64) -> String {
+    let minutes = (time / 60.0).floor();
+    format!("{}:{:05.2}", minutes, time - minutes * 60.0)
+}
+
 impl AppContext for Player {
     fn show<'a>(&mut self, ui: &Ui<'a>) -> bool {
-        ui.child_frame(im_str!("player"), CONFIG.player.player_frame_size)
+        let mut device_changed = false;
+        let mut rescan = false;
+        ui.child_frame(im_str!("player"), config().player.player_frame_size)
             .show_borders(true)
             .build(|| {
+                let overlay = match self.song.device() {
+                    Some(_) => ImString::new(format_time(self.position())),
+                    None => ImString::new("no output device")
+                };
                 ui.progress_bar(self.progress())
                     .size((-1.0, 0.0))
-                    .overlay_text(im_str!("{:.2}", to_f(self.song.progress())))
+                    .overlay_text(&overlay)
                     .build();
                 ui.text(format!("{:.2}", self.start()));
                 ui.same_line(300.0);
@@ -121,10 +194,25 @@ impl AppContext for Player {
                 if ui.button(im_str!("pause"), (0.0, 0.0)) {
                     self.pause();
                 }
+                ui.same_line(0.0);
+                if ui.checkbox(im_str!("loop"), &mut self.looping) {
+                    self.song.set_looping(self.looping);
+                }
+                let devices = self.devices.iter().map(|d| d.as_ref()).collect::<Vec<&ImStr>>();
+                ui.with_item_width(120.0, || {
+                    device_changed = ui.combo(im_str!("##device"), &mut self.device, &devices, 5);
+                });
+                ui.same_line(0.0);
+                rescan = ui.button(im_str!("rescan"), (0.0, 0.0));
             });
 
+        if device_changed {
+            self.select_device();
+        }
+        if rescan {
+            self.refresh_devices();
+        }
         self.update_volume();
-        self.log_load_status();
 
         true
     }
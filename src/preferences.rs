@@ -0,0 +1,171 @@
+
+use imgui::*;
+
+use configuration::{self, Config};
+use constants::MAX_PATH_LEN;
+use console::Logger;
+
+/// Edits the user settings layer and writes it to the user settings file, overrides
+/// of the working directory, project and environment aren't shown or saved.
+/// Sizes of already opened windows and fonts are applied on the next start
+pub struct Preferences {
+    logger: Logger,
+    config: Config,
+    tab_lang: ImString,
+    base_dir: ImString,
+    error: Option<String>,
+}
+
+impl Preferences {
+    pub fn new(logger: Logger) -> Self {
+        let (config, error) = match configuration::user_config() {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(format!("Can't read user settings, showing defaults: {}", e)))
+        };
+        let mut preferences = Preferences {
+            logger,
+            config,
+            tab_lang: ImString::with_capacity(MAX_PATH_LEN),
+            base_dir: ImString::with_capacity(MAX_PATH_LEN),
+            error: None,
+        };
+        preferences.reset_buffers();
+        preferences.error = error;
+        preferences
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>) -> bool {
+        let mut opened = true;
+        let mut saved = false;
+        ui.window(im_str!("Preferences"))
+            .opened(&mut opened)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                self.show_settings(ui);
+                ui.separator();
+                if let Some(ref error) = self.error {
+                    ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("{}", error));
+                }
+                if ui.button(im_str!("Save"), (0.0, 0.0)) {
+                    saved = self.save();
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Defaults"), (0.0, 0.0)) {
+                    self.config = Config::default();
+                    self.reset_buffers();
+                }
+            });
+
+        opened && !saved
+    }
+
+    fn show_settings<'a>(&mut self, ui: &Ui<'a>) {
+        let config = &mut self.config;
+        if ui.collapsing_header(im_str!("State")).default_open(true).build() {
+            input_usize(ui, im_str!("lyrics text size"), &mut config.state.default_lyrics_text_size);
+            ui.input_text(im_str!("default language"), &mut self.tab_lang).build();
+        }
+        if ui.collapsing_header(im_str!("Dialogs")).build() {
+            ui.input_text(im_str!("base directory"), &mut self.base_dir).build();
+            input_pair(ui, im_str!("dialog size"), &mut config.dialogs.dialog_sizes);
+            ui.input_float(im_str!("file browser width"), &mut config.dialogs.file_browser_width).build();
+            ui.input_float(im_str!("file browser height"), &mut config.dialogs.file_browser_height).build();
+        }
+        if ui.collapsing_header(im_str!("Player")).build() {
+            input_pair(ui, im_str!("player size"), &mut config.player.player_frame_size);
+            ui.slider_float(im_str!("default volume"), &mut config.player.default_volume, 0.0, 100.0)
+                .display_format(im_str!("%.0f"))
+                .build();
+            let mut seek_step = config.player.seek_step as i32;
+            if ui.input_int(im_str!("seek step (s)"), &mut seek_step).build() {
+                config.player.seek_step = seek_step.max(0) as u32;
+            }
+            ui.input_float(im_str!("output latency (ms)"), &mut config.player.output_latency_ms).build();
+            input_u32(ui, im_str!("fade (ms)"), &mut config.player.fade_ms);
+            input_u32(ui, im_str!("loop crossfade (ms)"), &mut config.player.loop_crossfade_ms);
+        }
+        if ui.collapsing_header(im_str!("Repetitions")).build() {
+            let repetitions = &mut config.repetitions;
+            if ui.input_float(im_str!("frame (ms)"), &mut repetitions.frame_ms).build() {
+                repetitions.frame_ms = repetitions.frame_ms.max(1.0);
+            }
+            if ui.input_float(im_str!("min length (s)"), &mut repetitions.min_length_secs).build() {
+                repetitions.min_length_secs = repetitions.min_length_secs.max(0.1);
+            }
+            ui.slider_float(im_str!("similarity"), &mut repetitions.similarity, 0.0, 1.0).build();
+            input_usize(ui, im_str!("max results"), &mut repetitions.max_results);
+            repetitions.max_results = repetitions.max_results.max(1);
+        }
+        if ui.collapsing_header(im_str!("Console")).build() {
+            input_pair(ui, im_str!("console position"), &mut config.console.console_pos);
+            input_pair(ui, im_str!("console size"), &mut config.console.console_size);
+            input_usize(ui, im_str!("history length"), &mut config.console.history_len);
+        }
+        if ui.collapsing_header(im_str!("Main window")).build() {
+            let main_window = &mut config.main_window;
+            input_pair(ui, im_str!("window size"), &mut main_window.main_window_size);
+            input_pair(ui, im_str!("quatrains size"), &mut main_window.quatrains_frame_size);
+            input_usize(ui, im_str!("tooltip length"), &mut main_window.tooltip_len);
+            input_usize(ui, im_str!("language name length"), &mut main_window.lang_name_len);
+            ui.input_float(im_str!("lyrics width"), &mut main_window.lyrics_input_width).build();
+            ui.input_float(im_str!("lyrics height"), &mut main_window.lyrics_input_height).build();
+            ui.input_float(im_str!("column offset"), &mut main_window.column_offset).build();
+            ui.input_float(im_str!("song path width"), &mut main_window.song_path_input_len).build();
+            ui.input_float(im_str!("tooltip width"), &mut main_window.timeframe_tooltip_width).build();
+            ui.input_float(im_str!("new language width"), &mut main_window.new_lang_input_width).build();
+            input_pair(ui, im_str!("word tapper size"), &mut main_window.word_tapper_size);
+            input_pair(ui, im_str!("side by side size"), &mut main_window.parallel_editor_size);
+            input_usize(ui, im_str!("side by side line length"), &mut main_window.parallel_line_len);
+            let mut occurrence_len = main_window.new_occurrence_len as f32;
+            if ui.input_float(im_str!("new section length (s)"), &mut occurrence_len).build() {
+                main_window.new_occurrence_len = occurrence_len.max(0.1) as f64;
+            }
+        }
+    }
+
+    fn save(&mut self) -> bool {
+        self.config.state.default_tab_lang = self.tab_lang.to_str().trim().to_owned();
+        self.config.dialogs.base_dir = self.base_dir.to_str().trim().to_owned();
+
+        match configuration::save(self.config.clone()) {
+            Ok(_) => {
+                self.logger.info("Settings saved");
+                true
+            }
+            Err(e) => {
+                self.error = Some(format!("{}", e));
+                false
+            }
+        }
+    }
+
+    fn reset_buffers(&mut self) {
+        self.tab_lang.clear();
+        self.tab_lang.push_str(&self.config.state.default_tab_lang);
+        self.base_dir.clear();
+        self.base_dir.push_str(&self.config.dialogs.base_dir);
+        self.error = None;
+    }
+}
+
+fn input_pair<'a>(ui: &Ui<'a>, label: &ImStr, value: &mut (f32, f32)) {
+    let mut pair = [value.0, value.1];
+    if ui.input_float2(label, &mut pair).build() {
+        *value = (pair[0], pair[1]);
+    }
+}
+
+fn input_u32<'a>(ui: &Ui<'a>, label: &ImStr, value: &mut u32) {
+    let mut int = *value as i32;
+    if ui.input_int(label, &mut int).build() {
+        *value = int.max(0) as u32;
+    }
+}
+
+fn input_usize<'a>(ui: &Ui<'a>, label: &ImStr, value: &mut usize) {
+    let mut int = *value as i32;
+    if ui.input_int(label, &mut int).build() {
+        *value = int.max(0) as usize;
+    }
+}
@@ -0,0 +1,133 @@
+
+use imgui::*;
+
+use analysis::Repetition;
+use player::{Player, format_time};
+use song::TimeSpan;
+use state::TimeFrame;
+use timing;
+
+/// Repeated parts found in the song. Frames timed once can be cloned onto
+/// every repetition together with their lyrics
+pub struct RepetitionList {
+    repetitions: Vec<Repetition>,
+    /// Frame cloned onto its repetitions
+    frame: i32,
+    status: Option<String>,
+}
+
+impl RepetitionList {
+    pub fn new(repetitions: Vec<Repetition>) -> Self {
+        RepetitionList {
+            repetitions,
+            frame: 0,
+            status: None,
+        }
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr, timings: &mut Vec<TimeFrame>, player: &mut Player) -> bool {
+        let mut opened = true;
+        let mut play = None;
+        let mut clone = None;
+        let mut clone_frame = false;
+        let names = timings.iter()
+            .map(|f| ImString::new(format!("{} {}", format_time(f.start_secs()), f.title())))
+            .collect::<Vec<_>>();
+        let names = names.iter().map(|n| n.as_ref()).collect::<Vec<&ImStr>>();
+
+        ui.window(title)
+            .opened(&mut opened)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                if self.repetitions.is_empty() {
+                    ui.text(im_str!("No repeated parts were found"));
+                }
+                for (idx, repetition) in self.repetitions.iter().enumerate() {
+                    ui.with_id(idx as i32, || {
+                        ui.text(im_str!("{} - {} repeats at {} ({:.0}%)",
+                                        format_time(repetition.source.0),
+                                        format_time(repetition.source.1),
+                                        format_time(repetition.target.0),
+                                        repetition.similarity * 100.0));
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("play"), (0.0, 0.0)) {
+                            play = Some(idx);
+                        }
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("clone frames"), (0.0, 0.0)) {
+                            clone = Some(idx);
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Copies frames of the first part onto the repetition");
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.with_item_width(200.0, || {
+                    ui.combo(im_str!("##frame"), &mut self.frame, &names, 10);
+                });
+                ui.same_line(0.0);
+                if ui.button(im_str!("clone onto repetitions"), (0.0, 0.0)) && !names.is_empty() {
+                    clone_frame = true;
+                }
+                if let Some(ref status) = self.status {
+                    ui.text(im_str!("{}", status));
+                }
+            });
+
+        if let Some(repetition) = play.map(|idx| self.repetitions[idx]) {
+            let (start, end) = repetition.target;
+            player.update(TimeSpan::new(start as u32, (end - start).ceil() as u32));
+            player.play();
+        }
+        // cloning sorts the frames, the chosen frame is found again by its times
+        let chosen = timings.get(self.frame as usize).map(|f| (f.start, f.end));
+        if let Some(repetition) = clone.map(|idx| self.repetitions[idx]) {
+            let (start, end) = repetition.source;
+            let frames = (0..timings.len())
+                .filter(|&idx| start <= middle(&timings[idx]) && middle(&timings[idx]) < end)
+                .collect::<Vec<_>>();
+            let added = timing::clone_shifted(timings, &frames, repetition.offset());
+            self.status = Some(format!("{} frames were cloned", added));
+        }
+        if clone_frame && (self.frame as usize) < timings.len() {
+            let mut idx = self.frame as usize;
+            let (start, end) = (timings[idx].start, timings[idx].end);
+            let mut added = 0;
+            for offset in self.offsets(middle(&timings[idx])) {
+                added += timing::clone_shifted(timings, &[idx], offset);
+                // Frames are sorted after cloning, so the frame may have moved
+                idx = timings.iter().position(|f| f.start == start && f.end == end).unwrap_or(idx);
+            }
+            self.status = Some(format!("{} frames were cloned", added));
+        }
+        if let Some((start, end)) = chosen {
+            if let Some(idx) = timings.iter().position(|f| f.start == start && f.end == end) {
+                self.frame = idx as i32;
+            }
+        }
+
+        opened
+    }
+
+    /// Offsets from `time` to every repetition of it. Repetitions are sorted
+    /// by target, so repetitions of repetitions are found in one pass
+    fn offsets(&self, time: f64) -> Vec<f64> {
+        let mut times = vec![time];
+        for repetition in &self.repetitions {
+            let (start, end) = repetition.source;
+            let found = times.iter()
+                .filter(|&&t| start <= t && t < end)
+                .map(|t| t + repetition.offset())
+                .collect::<Vec<_>>();
+            times.extend(found);
+        }
+        times.into_iter().skip(1).map(|t| t - time).collect()
+    }
+}
+
+fn middle(frame: &TimeFrame) -> f64 {
+    (frame.start_secs() + frame.end_secs()) / 2.0
+}
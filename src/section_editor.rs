@@ -0,0 +1,113 @@
+
+use imgui::*;
+
+use configuration::config;
+use constants::MAX_PATH_LEN;
+use state::{Section, TimeFrame, ImLanguageTab};
+
+/// Edits the name and the text of a shared section in every language.
+/// Changes apply to every frame linked to the section
+pub struct SectionEditor {
+    section: usize,
+    name: ImString,
+    /// Language tags of the text buffers, they are refilled when tabs change
+    langs: Vec<String>,
+    texts: Vec<ImString>,
+    error: Option<String>,
+}
+
+impl SectionEditor {
+    pub fn new(idx: usize, section: &Section) -> Self {
+        let mut name = ImString::with_capacity(MAX_PATH_LEN);
+        name.push_str(&section.name);
+
+        SectionEditor {
+            section: idx,
+            name,
+            langs: Vec::new(),
+            texts: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn show<'a>(&mut self, ui: &Ui<'a>, title: &ImStr, sections: &mut [Section],
+                    lyrics: &[ImLanguageTab], timings: &mut [TimeFrame]) -> bool {
+        if self.section >= sections.len() {
+            return false;
+        }
+        self.refill(&sections[self.section], lyrics);
+
+        let mut opened = true;
+        let mut rename = false;
+        ui.window(title)
+            .opened(&mut opened)
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+                ui.input_text(im_str!("##name"), &mut self.name).build();
+                ui.same_line(0.0);
+                rename = ui.button(im_str!("rename"), (0.0, 0.0));
+                if let Some(ref error) = self.error {
+                    ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("{}", error));
+                }
+
+                let section = &mut sections[self.section];
+                let occurrences = timings.iter()
+                    .filter(|f| f.shared.as_ref() == Some(&section.name))
+                    .count();
+                ui.text(im_str!("used by {} frames", occurrences));
+                for (idx, tab) in lyrics.iter().enumerate() {
+                    ui.with_id(idx as i32, || {
+                        ui.text(im_str!("{} ({})", tab.name, tab.lang));
+                        if ui.input_text(im_str!("##text"), &mut self.texts[idx])
+                            .multiline(ImVec2::new(300.0, 80.0))
+                            .build()
+                        {
+                            section.set_text(&self.langs[idx], self.texts[idx].to_str());
+                        }
+                    });
+                }
+            });
+
+        if rename {
+            self.rename(sections, timings);
+        }
+
+        opened
+    }
+
+    fn refill(&mut self, section: &Section, lyrics: &[ImLanguageTab]) {
+        let langs = lyrics.iter().map(|t| t.lang.to_str().to_owned()).collect::<Vec<_>>();
+        if langs != self.langs {
+            self.texts = langs.iter()
+                .map(|lang| {
+                    let mut text = ImString::with_capacity(config().state.default_lyrics_text_size);
+                    text.push_str(section.text(lang));
+                    text
+                })
+                .collect();
+            self.langs = langs;
+        }
+    }
+
+    /// Linked frames follow the new name, names must stay unique
+    fn rename(&mut self, sections: &mut [Section], timings: &mut [TimeFrame]) {
+        let name = self.name.to_str().trim().to_owned();
+        let old = sections[self.section].name.clone();
+        self.error = if name.is_empty() {
+            Some("The name is empty".into())
+        } else if name != old && sections.iter().any(|s| s.name == name) {
+            Some(format!("Section '{}' already exists", name))
+        } else {
+            None
+        };
+        if self.error.is_some() || name == old {
+            return;
+        }
+
+        for frame in timings.iter_mut().filter(|f| f.shared.as_ref() == Some(&old)) {
+            frame.shared = Some(name.clone());
+        }
+        sections[self.section].name = name;
+    }
+}
@@ -0,0 +1,80 @@
+
+use failure::Error;
+use serde_json;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use configuration::user_dir;
+use constants::{SESSION_FILE, LAYOUT_FILE, MAX_RECENT_PROJECTS};
+
+/// State restored on the next start: recently used projects and the project
+/// which was open on exit. Stored in the user directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    recent: Vec<PathBuf>,
+    #[serde(default)]
+    last_project: Option<PathBuf>,
+}
+
+impl Session {
+    /// Empty session if there is no session file or it can't be read
+    pub fn load() -> Self {
+        user_dir()
+            .and_then(|dir| File::open(dir.join(SESSION_FILE)).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let dir = user_dir().ok_or(format_err!("Can't find user directory"))?;
+        fs::create_dir_all(&dir)?;
+        serde_json::to_writer_pretty(File::create(dir.join(SESSION_FILE))?, self)?;
+        Ok(())
+    }
+
+    /// Most recently used first
+    pub fn recent(&self) -> &[PathBuf] {
+        &self.recent
+    }
+
+    pub fn last_project(&self) -> Option<&Path> {
+        self.last_project.as_ref().map(|p| p.as_path())
+    }
+
+    /// Makes the project the last one and moves it to the top of the recent list.
+    /// Returns `false` if nothing changed
+    pub fn set_project(&mut self, project: Option<&Path>) -> bool {
+        if self.last_project.as_ref().map(|p| p.as_path()) == project {
+            return false;
+        }
+
+        self.last_project = project.map(Path::to_path_buf);
+        if let Some(project) = project {
+            self.recent.retain(|p| p != project);
+            self.recent.insert(0, project.to_path_buf());
+            self.recent.truncate(MAX_RECENT_PROJECTS);
+        }
+        true
+    }
+
+    /// Forgets a project which can't be opened anymore
+    pub fn remove(&mut self, project: &Path) {
+        self.recent.retain(|p| p != project);
+        if self.last_project() == Some(project) {
+            self.last_project = None;
+        }
+    }
+
+    pub fn clear_recent(&mut self) {
+        self.recent.clear();
+    }
+}
+
+/// File where imgui keeps window positions and sizes
+pub fn layout_file() -> Option<PathBuf> {
+    let dir = user_dir()?;
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(LAYOUT_FILE))
+}
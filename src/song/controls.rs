@@ -2,25 +2,51 @@
 use super::TimeSpan;
 
 use std::sync::Mutex;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub struct Controls {
     pub stopped: AtomicBool,
     pub paused: AtomicBool,
+    pub restart: AtomicBool,
+    pub released: AtomicBool,
+    pub looping: AtomicBool,
+    /// Fade length on state changes in milliseconds
+    pub fade_ms: AtomicUsize,
+    /// Crossfade length of looped time spans in milliseconds
+    pub crossfade_ms: AtomicUsize,
+    pub reconnect: AtomicBool,
+    /// Bumped each time the song is connected to a device
+    pub connection: AtomicUsize,
+    /// Bumped each time a song is opened
+    pub track: AtomicUsize,
+    pub device: Mutex<Option<String>>,
     pub time: Mutex<TimeSpan>,
     pub volume: Mutex<f32>,
-    pub progress: Mutex<u32>,
+    /// Index of the last sample pulled by the output, interleaved by channels
+    pub cursor: AtomicUsize,
+    /// Samples per second of all channels
+    pub rate: AtomicUsize,
 }
 
 impl Controls {
     #[inline]
     pub fn new() -> Self {
         Controls {
-            stopped: false.into(),
+            stopped: true.into(),
             paused: false.into(),
+            restart: false.into(),
+            released: false.into(),
+            looping: false.into(),
+            fade_ms: AtomicUsize::new(0),
+            crossfade_ms: AtomicUsize::new(0),
+            reconnect: false.into(),
+            connection: AtomicUsize::new(0),
+            track: AtomicUsize::new(0),
+            device: Mutex::new(None),
             time: Mutex::new(TimeSpan::default()),
             volume: Mutex::new(1.0),
-            progress: Mutex::new(0),
+            cursor: AtomicUsize::new(0),
+            rate: AtomicUsize::new(0),
         }
     }
 
@@ -44,6 +70,105 @@ impl Controls {
         self.paused.store(value, Ordering::SeqCst);
     }
 
+    /// Returns whether the cursor must be moved to the start of the time span
+    /// without clearing the request
+    #[inline]
+    pub fn restart(&self) -> bool {
+        self.restart.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the cursor must be moved to the start of the time span
+    #[inline]
+    pub fn take_restart(&self) -> bool {
+        self.restart.swap(false, Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn set_restart(&self, value: bool) {
+        self.restart.store(value, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn looping(&self) -> bool {
+        self.looping.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn set_looping(&self, value: bool) {
+        self.looping.store(value, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn fades(&self) -> (usize, usize) {
+        (self.fade_ms.load(Ordering::SeqCst), self.crossfade_ms.load(Ordering::SeqCst))
+    }
+
+    #[inline]
+    pub fn set_fades(&self, fade_ms: usize, crossfade_ms: usize) {
+        self.fade_ms.store(fade_ms, Ordering::SeqCst);
+        self.crossfade_ms.store(crossfade_ms, Ordering::SeqCst);
+    }
+
+    /// Set once the song owning the controls is dropped
+    #[inline]
+    pub fn released(&self) -> bool {
+        self.released.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn release(&self) {
+        self.released.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the song must be connected to a device again
+    #[inline]
+    pub fn take_reconnect(&self) -> bool {
+        self.reconnect.swap(false, Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn set_reconnect(&self) {
+        self.reconnect.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn connection(&self) -> usize {
+        self.connection.load(Ordering::SeqCst)
+    }
+
+    /// Sources of the connection must end
+    #[inline]
+    pub fn is_detached(&self, connection: usize) -> bool {
+        self.released() || self.connection() != connection
+    }
+
+    /// Detaches sources playing on the previous device
+    #[inline]
+    pub fn next_connection(&self) -> usize {
+        self.connection.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    #[inline]
+    pub fn track(&self) -> usize {
+        self.track.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn next_track(&self) -> usize {
+        self.track.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Name of the device the song plays on, `None` if playback is disabled
+    #[inline]
+    pub fn device(&self) -> Option<String> {
+        self.device.lock().unwrap().clone()
+    }
+
+    #[inline]
+    pub fn set_device(&self, value: Option<String>) {
+        *self.device.lock().unwrap() = value;
+    }
+
     #[inline]
     pub fn volume(&self) -> f32 {
         *self.volume.lock().unwrap()
@@ -64,14 +189,33 @@ impl Controls {
         *self.time.lock().unwrap() = value;
     }
 
+    /// Called by the audio thread for every sample, so it must stay cheap
     #[inline]
-    pub fn progress(&self) -> u32 {
-        *self.progress.lock().unwrap()
+    pub fn set_cursor(&self, value: usize) {
+        self.cursor.store(value, Ordering::Relaxed);
     }
 
     #[inline]
-    pub fn set_progress(&self, value: u32) {
-        *self.progress.lock().unwrap() = value;
+    pub fn set_rate(&self, value: usize) {
+        self.rate.store(value, Ordering::SeqCst);
+    }
+
+    /// Seconds from the song start of the sample which is being heard.
+    /// Samples pulled but still buffered by the output are not counted,
+    /// paused too, so the position doesn't jump on pause and resume.
+    /// A stopped song is rewound to the start of its time span
+    pub fn position(&self, latency: f64) -> f64 {
+        let rate = self.rate.load(Ordering::SeqCst);
+        if rate == 0 {
+            return 0.0;
+        }
+
+        let pulled = self.cursor.load(Ordering::Relaxed) as f64 / rate as f64;
+        if self.stopped() {
+            pulled
+        } else {
+            (pulled - latency).max(0.0)
+        }
     }
 }
 
@@ -2,8 +2,12 @@
 mod song;
 mod sources;
 mod controls;
+mod monitor;
+mod output;
+mod sink;
 
 pub use self::song::*;
+pub use self::output::{output_devices, open_sink, Track};
 pub use self::sources::Sample;
 
 use rodio::Source;
@@ -15,6 +19,8 @@ pub trait DirectAccess: Source + fmt::Debug
 {
     fn current(&self) -> usize;
     fn set_current(&mut self, index: usize);
+    /// Sample at `index` without moving the cursor
+    fn get(&self, index: usize) -> Option<Self::Item>;
 }
 
 pub trait Resettable: Source + fmt::Debug 
@@ -31,23 +37,7 @@ pub trait FloatWindow: Resettable
     fn cursor(&self) -> usize;
 }
 
-pub trait Inspectable
-    where Self: FloatWindow,
-          <Self as Iterator>::Item: ::rodio::Sample
-{
-    fn current_sec(&self) -> usize;
-}
-
-impl<T> Inspectable for T
-    where T: FloatWindow,
-          <T as Iterator>::Item: ::rodio::Sample
-{
-    fn current_sec(&self) -> usize {
-        self.cursor() / self.channels() as usize / self.sample_rate() as usize
-    }
-}
-
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq)]
 pub struct TimeSpan {
     pub start: u32,
     pub duration: u32
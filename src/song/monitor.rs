@@ -0,0 +1,71 @@
+
+use std::time::{Duration, Instant};
+
+use constants::{MONITOR_INTERVAL, UNDERRUN_THRESHOLD, DRIFT_THRESHOLD};
+use console::Logger;
+
+/// Runs on the audio thread once per `MONITOR_INTERVAL` of pulled samples,
+/// warnings are only sent to the UI thread which writes them.
+/// A long gap between two runs means the device was starved, a growing
+/// difference between the wall clock and the samples played is drift
+pub struct PlaybackMonitor {
+    logger: Logger,
+    last: Option<Instant>,
+    started: Option<Instant>,
+    played: Duration,
+}
+
+impl PlaybackMonitor {
+    pub fn new(logger: Logger) -> Self {
+        PlaybackMonitor { logger, last: None, started: None, played: Duration::from_secs(0) }
+    }
+
+    pub fn interval() -> Duration {
+        Duration::from_millis(MONITOR_INTERVAL)
+    }
+
+    pub fn update(&mut self, playing: bool) {
+        if !playing {
+            self.last = None;
+            self.started = None;
+            return;
+        }
+
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let gap = now.duration_since(last);
+            if gap > Duration::from_millis(UNDERRUN_THRESHOLD) {
+                self.logger.warn(format!("Audio buffer underrun, no samples requested for {} ms", millis(gap)));
+                // the gap isn't drift, measure from here
+                self.started = None;
+            }
+        }
+        self.last = Some(now);
+
+        match self.started {
+            Some(started) => {
+                self.played += Self::interval();
+                let elapsed = now.duration_since(started);
+                let (drift, ahead) = if elapsed > self.played {
+                    (elapsed - self.played, false)
+                } else {
+                    (self.played - elapsed, true)
+                };
+                if drift > Duration::from_millis(DRIFT_THRESHOLD) {
+                    self.logger.warn(format!("Playback drifted {} ms {} the clock", millis(drift),
+                                             if ahead { "ahead of" } else { "behind" }));
+                    self.started = Some(now);
+                    self.played = Duration::from_secs(0);
+                }
+            }
+            None => {
+                self.started = Some(now);
+                self.played = Duration::from_secs(0);
+            }
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+}
@@ -0,0 +1,293 @@
+
+use rodio::{self, Device, Source};
+
+use std::path::PathBuf;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use constants::{DEVICE_POLL_INTERVAL, RECONNECT_CHECK_INTERVAL};
+use configuration::config;
+use console::Logger;
+use super::{TimeSpan, FloatWindow, Resettable, Sample};
+use super::controls::Controls;
+use super::monitor::PlaybackMonitor;
+use super::sink::{Activity, AudioSink, DeviceSink, NullSink, WavSink};
+use super::sources::{
+    SmartSource,
+    BaseSource,
+    FloatWindowSource,
+    StoppableSource,
+    PausableSource,
+    FadeSource
+};
+
+/// Names of the available output devices
+pub fn output_devices() -> Vec<String> {
+    rodio::output_devices().map(|d| d.name()).collect()
+}
+
+/// Device chosen in settings if it's connected, the default one otherwise
+fn find_device() -> Option<Device> {
+    let preferred = config().player.output_device.clone();
+    preferred
+        .and_then(|name| rodio::output_devices().find(|d| d.name() == name))
+        .or_else(rodio::default_output_device)
+}
+
+/// Sink selected by `sink` in the `[player]` section of settings
+pub fn open_sink(logger: &Logger) -> Option<Box<AudioSink>> {
+    let config = config();
+    match config.player.sink.as_str() {
+        "null" => Some(Box::new(NullSink)),
+        "wav" => Some(Box::new(WavSink::new(logger.clone(), PathBuf::from(&config.player.sink_file)))),
+        _ => find_device().map(|d| Box::new(DeviceSink::new(d)) as Box<AudioSink>)
+    }
+}
+
+/// State of the controls last applied to the sources
+#[derive(Copy, Clone, PartialEq)]
+struct Transport {
+    stopped: bool,
+    paused: bool,
+    time: TimeSpan,
+}
+
+/// Decoded song which can be connected to a device again
+#[derive(Clone)]
+pub struct Track {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Arc<Vec<Sample>>,
+}
+
+/// Keeps the song connected to an output device. Runs on the thread which
+/// decoded the song until the song is dropped or another one is opened,
+/// then the sources end and the samples are freed with the last of them
+pub struct Output {
+    logger: Logger,
+    controls: Arc<Controls>,
+    track: Track,
+    id: usize,
+    /// Sink of the last connection, reused by the next one if it can be
+    sink: Option<Box<AudioSink>>,
+}
+
+impl Output {
+    /// `id` is the track number given to the song when it was opened
+    pub fn new(logger: Logger, controls: Arc<Controls>, track: Track, id: usize) -> Self {
+        Output { logger, controls, track, id, sink: None }
+    }
+
+    /// Another song was opened while this one was decoding or the song was dropped
+    fn is_current(&self) -> bool {
+        !self.controls.released() && self.controls.track() == self.id
+    }
+
+    pub fn run(mut self) {
+        if !self.is_current() {
+            return;
+        }
+
+        self.connect();
+        if self.controls.device().is_none() {
+            self.logger.warn("No output device found, playback is disabled");
+        }
+
+        let mut last_poll = Instant::now();
+        loop {
+            thread::sleep(Duration::from_millis(RECONNECT_CHECK_INTERVAL));
+            if !self.is_current() {
+                break;
+            }
+
+            let poll = last_poll.elapsed() >= Duration::from_millis(DEVICE_POLL_INTERVAL);
+            if poll {
+                last_poll = Instant::now();
+            }
+            if self.controls.take_reconnect() || (poll && self.device_changed()) {
+                self.connect();
+            }
+        }
+    }
+
+    /// The device is gone, another one was plugged in, the default one
+    /// or the sink in settings changed
+    fn device_changed(&self) -> bool {
+        let current = self.controls.device();
+        match config().player.sink.as_str() {
+            "null" => current.as_ref().map(|s| s.as_str()) != Some("null"),
+            "wav" => current != Some(format!("file {}", PathBuf::from(&config().player.sink_file).display())),
+            _ => find_device().map(|d| d.name()) != current
+        }
+    }
+
+    /// Without a device the song stays loaded with playback disabled until
+    /// a device appears
+    fn connect(&mut self) {
+        let connection = self.controls.next_connection();
+        let previous = self.controls.device();
+        let sink = match self.sink.take() {
+            Some(sink) if sink.is_reusable() && !self.device_changed() => Some(sink),
+            _ => open_sink(&self.logger)
+        };
+        match sink {
+            Some(mut sink) => {
+                let name = sink.name();
+                self.play(&mut *sink, connection);
+                self.sink = Some(sink);
+                match previous {
+                    Some(ref previous) if *previous != name =>
+                        self.logger.warn(format!("Output device changed from {} to {}", previous, name)),
+                    _ => self.logger.info(format!("Playing on {}", name))
+                }
+                self.controls.set_device(Some(name));
+            }
+            None => {
+                if let Some(previous) = previous {
+                    self.logger.warn(format!("Output device {} disconnected, playback is disabled", previous));
+                }
+                self.controls.set_device(None);
+            }
+        }
+    }
+
+    fn play(&self, sink: &mut AudioSink, connection: usize) {
+        let controls = self.controls.clone();
+        let controls2 = self.controls.clone();
+        let controls3 = self.controls.clone();
+        let mut applied = None;
+        let mut monitor = PlaybackMonitor::new(self.logger.clone());
+
+        let track = self.track.clone();
+        self.controls.set_rate(track.channels as usize * track.sample_rate as usize);
+        let source = BaseSource::new(track.channels, track.sample_rate, track.samples);
+        let source = FloatWindowSource::new(source);
+        let source = StoppableSource::new(source);
+        let source = PausableSource::new(source);
+        let source = SmartSource::new(source, self.controls.clone(), connection);
+        let source = FadeSource::new(source)
+            .amplify(1.0)
+            .periodic_access(Duration::from_millis(5), move |src| {
+                let (fade_ms, crossfade_ms) = controls.fades();
+                let samples_per_ms = src.sample_rate() as usize * src.channels() as usize / 1000;
+                src.set_factor(controls.volume());
+
+                // changes are applied once the fade out is over and faded in again
+                let fade = src.inner_mut();
+                fade.set_length(fade_ms * samples_per_ms);
+                fade.set_fades(fade_ms * samples_per_ms, crossfade_ms * samples_per_ms);
+                fade.set_looping(controls.looping());
+                let target = Transport {
+                    stopped: controls.stopped(),
+                    paused: controls.paused(),
+                    time: controls.time(),
+                };
+                if applied != Some(target) || controls.restart() {
+                    if !fade.is_silent() {
+                        fade.fade_out();
+                        return;
+                    }
+                    fade.stop(target.stopped);
+                    fade.pause(target.paused);
+                    fade.play(target.time);
+                    if controls.take_restart() {
+                        fade.reset();
+                    }
+                    applied = Some(target);
+                }
+                if !target.stopped && !target.paused {
+                    fade.fade_in();
+                }
+            })
+        .periodic_access(PlaybackMonitor::interval(), move |_| {
+            monitor.update(!controls2.stopped() && !controls2.paused());
+        })
+        .convert_samples();
+
+        let active: Activity = Arc::new(move || !controls3.stopped() && !controls3.paused());
+        sink.play(Box::new(source), active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use song::sink::PullSink;
+
+    /// One channel at 1000 Hz, so a sample is a millisecond
+    fn output(controls: &Arc<Controls>) -> Output {
+        let track = Track { channels: 1, sample_rate: 1000, samples: Arc::new(vec![16384; 3000]) };
+        Output::new(Logger::new(), controls.clone(), track, 0)
+    }
+
+    fn connect(controls: &Arc<Controls>) -> PullSink {
+        let mut sink = PullSink::new();
+        output(controls).play(&mut sink, controls.next_connection());
+        sink
+    }
+
+    fn playing() -> Arc<Controls> {
+        let controls = Arc::new(Controls::new());
+        controls.set_fades(10, 0);
+        controls.set_time(TimeSpan::new(0, 2));
+        controls.set_stopped(false);
+        controls
+    }
+
+    #[test]
+    fn stopped_song_is_silent() {
+        let controls = Arc::new(Controls::new());
+        let sink = connect(&controls);
+        assert!(sink.pull(100).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn playing_song_fades_in() {
+        let controls = playing();
+        let sink = connect(&controls);
+        let samples = sink.pull(500);
+        assert_eq!(samples.len(), 500);
+        assert!(samples[1] < 0.5);
+        assert!((samples[499] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn paused_song_fades_out() {
+        let controls = playing();
+        let sink = connect(&controls);
+        sink.pull(500);
+        controls.set_paused(true);
+        let samples = sink.pull(100);
+        assert!(samples[0] > 0.0);
+        assert!(samples[50..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn song_stops_at_the_end_of_the_time_span() {
+        let controls = playing();
+        let sink = connect(&controls);
+        let samples = sink.pull(2100);
+        assert!(samples[2050..].iter().all(|&s| s == 0.0));
+        assert!(controls.stopped());
+    }
+
+    #[test]
+    fn detached_source_ends() {
+        let controls = playing();
+        let sink = connect(&controls);
+        assert_eq!(sink.pull(100).len(), 100);
+        controls.next_connection();
+        assert!(sink.pull(100).is_empty());
+    }
+
+    #[test]
+    fn stopped_song_ends_when_released() {
+        let controls = Arc::new(Controls::new());
+        let sink = connect(&controls);
+        assert_eq!(sink.pull(10).len(), 10);
+        controls.release();
+        assert!(sink.pull(10).is_empty());
+    }
+}
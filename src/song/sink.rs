@@ -0,0 +1,325 @@
+
+use rodio::{self, Device, Source};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use console::Logger;
+
+pub type BoxedSource = Box<Source<Item = f32> + Send>;
+
+/// Whether the song is playing. Samples pulled while it's stopped or paused
+/// are silence, recording sinks don't write them
+pub type Activity = Arc<Fn() -> bool + Send + Sync>;
+
+/// Destination of the audio produced by a song
+pub trait AudioSink {
+    /// Shown in the player and the log
+    fn name(&self) -> String;
+    /// Plays the source until it ends
+    fn play(&mut self, source: BoxedSource, active: Activity);
+    /// Whether the sink can play the next connection of the same song.
+    /// Devices are opened again, so a reconnect finds the current device
+    fn is_reusable(&self) -> bool {
+        false
+    }
+}
+
+/// Sound card output
+pub struct DeviceSink {
+    device: Device,
+}
+
+impl DeviceSink {
+    pub fn new(device: Device) -> Self {
+        DeviceSink { device }
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn name(&self) -> String {
+        self.device.name()
+    }
+
+    fn play(&mut self, source: BoxedSource, _active: Activity) {
+        rodio::play_raw(&self.device, source);
+    }
+}
+
+/// Discards samples, pulled at the playback rate so the song behaves as if
+/// it was playing. Lets songs load without a sound card
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn name(&self) -> String {
+        "null".to_owned()
+    }
+
+    fn play(&mut self, source: BoxedSource, _active: Activity) {
+        pump(source, |_| {});
+    }
+
+    fn is_reusable(&self) -> bool {
+        true
+    }
+}
+
+/// Writes 16 bit PCM WAV at the playback rate, only while the song is playing.
+/// Connections of the same song append to one file, which is created again
+/// for the next song. The header is updated about once a second, so the file
+/// stays valid while the song is playing
+pub struct WavSink {
+    logger: Logger,
+    path: PathBuf,
+    writer: Option<Arc<Mutex<WavWriter<BufWriter<File>>>>>,
+}
+
+impl WavSink {
+    pub fn new(logger: Logger, path: PathBuf) -> Self {
+        WavSink { logger, path, writer: None }
+    }
+}
+
+impl AudioSink for WavSink {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn play(&mut self, source: BoxedSource, active: Activity) {
+        let writer = match self.writer {
+            Some(ref writer) => writer.clone(),
+            None => match File::create(&self.path) {
+                Ok(file) => {
+                    let writer = WavWriter::new(BufWriter::new(file), source.channels(), source.sample_rate());
+                    let writer = Arc::new(Mutex::new(writer));
+                    self.writer = Some(writer.clone());
+                    writer
+                }
+                Err(e) => return self.logger.error(format!("Can't create {}: {}", self.path.display(), e))
+            }
+        };
+
+        // the song keeps playing when the file can't be written anymore
+        let mut failed = false;
+        let mut logger = self.logger.clone();
+        let path = self.path.clone();
+        pump(source, move |samples| {
+            if failed || !active() {
+                return;
+            }
+            let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = writer.write(samples) {
+                logger.error(format!("Can't write {}: {}", path.display(), e));
+                failed = true;
+            }
+        });
+    }
+
+    fn is_reusable(&self) -> bool {
+        true
+    }
+}
+
+/// Keeps the source until it's pulled by hand, so tests can drive the source
+/// chain sample by sample without a device, a clock or a thread
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct PullSink {
+    source: Arc<Mutex<Option<BoxedSource>>>,
+}
+
+#[cfg(test)]
+impl PullSink {
+    pub fn new() -> Self {
+        PullSink::default()
+    }
+
+    /// Next `count` samples, fewer once the source ends
+    pub fn pull(&self, count: usize) -> Vec<f32> {
+        match *self.source.lock().unwrap() {
+            Some(ref mut source) => source.by_ref().take(count).collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+impl AudioSink for PullSink {
+    fn name(&self) -> String {
+        "pull".to_owned()
+    }
+
+    fn play(&mut self, source: BoxedSource, _active: Activity) {
+        *self.source.lock().unwrap() = Some(source);
+    }
+}
+
+/// Pulls the source on a separate thread in 10 ms chunks paced by the wall
+/// clock until it ends
+fn pump<F>(mut source: BoxedSource, mut write: F)
+    where F: FnMut(&[f32]) + Send + 'static
+{
+    thread::spawn(move || {
+        let rate = source.sample_rate() as u64 * source.channels() as u64;
+        let chunk = (rate / 100).max(1) as usize;
+        let mut buffer = Vec::with_capacity(chunk);
+        let started = Instant::now();
+        let mut pulled = 0u64;
+
+        loop {
+            buffer.clear();
+            buffer.extend(source.by_ref().take(chunk));
+            if buffer.is_empty() {
+                break;
+            }
+            write(&buffer);
+
+            pulled += buffer.len() as u64;
+            let due = Duration::from_millis(pulled * 1000 / rate);
+            let elapsed = started.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+    });
+}
+
+/// Size of the RIFF header before the samples
+const HEADER_LEN: u32 = 44;
+
+struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_len: u32,
+    /// Bytes written since the header was updated
+    pending: u32,
+    /// Bytes per second, the header is updated after each second
+    byte_rate: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    fn new(out: W, channels: u16, sample_rate: u32) -> Self {
+        let byte_rate = sample_rate * channels as u32 * 2;
+        let mut writer = WavWriter { out, data_len: 0, pending: 0, byte_rate };
+        // errors surface on the first write
+        let _ = writer.write_header(channels, sample_rate);
+        writer
+    }
+
+    fn write_header(&mut self, channels: u16, sample_rate: u32) -> io::Result<()> {
+        let block_align = channels as u32 * 2;
+        self.out.write_all(b"RIFF")?;
+        write_u32(&mut self.out, HEADER_LEN - 8)?;
+        self.out.write_all(b"WAVEfmt ")?;
+        write_u32(&mut self.out, 16)?;
+        write_u16(&mut self.out, 1)?;
+        write_u16(&mut self.out, channels)?;
+        write_u32(&mut self.out, sample_rate)?;
+        write_u32(&mut self.out, sample_rate * block_align)?;
+        write_u16(&mut self.out, block_align as u16)?;
+        write_u16(&mut self.out, 16)?;
+        self.out.write_all(b"data")?;
+        write_u32(&mut self.out, 0)
+    }
+
+    /// Fails once the file reaches the 4 GB limit of the format, the samples
+    /// which still fit are written
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let capacity = (u32::max_value() - HEADER_LEN - self.data_len) as usize / 2;
+        for &sample in samples.iter().take(capacity) {
+            let sample = (sample.max(-1.0).min(1.0) * i16::max_value() as f32) as i16;
+            write_u16(&mut self.out, sample as u16)?;
+        }
+        let written = samples.len().min(capacity) as u32 * 2;
+        self.data_len += written;
+        self.pending += written;
+
+        if samples.len() > capacity {
+            self.update_header()?;
+            return Err(io::Error::new(io::ErrorKind::Other, "WAV files can't be larger than 4 GB"));
+        }
+        if self.pending >= self.byte_rate {
+            self.update_header()?;
+        }
+        Ok(())
+    }
+
+    fn update_header(&mut self) -> io::Result<()> {
+        let position = self.out.seek(SeekFrom::Current(0))?;
+        self.out.seek(SeekFrom::Start(4))?;
+        write_u32(&mut self.out, HEADER_LEN - 8 + self.data_len)?;
+        self.out.seek(SeekFrom::Start(40))?;
+        write_u32(&mut self.out, self.data_len)?;
+        self.out.seek(SeekFrom::Start(position))?;
+        self.pending = 0;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.update_header();
+    }
+}
+
+fn write_u16<W: Write>(out: &mut W, value: u16) -> io::Result<()> {
+    out.write_all(&[value as u8, (value >> 8) as u8])
+}
+
+fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        bytes[at] as u32 | (bytes[at + 1] as u32) << 8 | (bytes[at + 2] as u32) << 16 | (bytes[at + 3] as u32) << 24
+    }
+
+    #[test]
+    fn header_counts_written_samples() {
+        let mut out = Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut out, 2, 1000);
+            writer.write(&[0.5; 100]).unwrap();
+        }
+
+        let bytes = out.into_inner();
+        assert_eq!(bytes.len(), HEADER_LEN as usize + 200);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(read_u32(&bytes, 4), HEADER_LEN - 8 + 200);
+        assert_eq!(read_u32(&bytes, 24), 1000);
+        assert_eq!(read_u32(&bytes, 40), 200);
+    }
+
+    #[test]
+    fn samples_are_clamped_16_bit() {
+        let mut out = Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut out, 1, 1000);
+            writer.write(&[2.0, -2.0, 0.0]).unwrap();
+        }
+
+        let bytes = out.into_inner();
+        let sample = |idx: usize| {
+            let at = HEADER_LEN as usize + idx * 2;
+            (bytes[at] as u16 | (bytes[at + 1] as u16) << 8) as i16
+        };
+        assert_eq!(sample(0), i16::max_value());
+        assert_eq!(sample(1), -i16::max_value());
+        assert_eq!(sample(2), 0);
+    }
+
+    #[test]
+    fn pull_sink_is_empty_until_played() {
+        let sink = PullSink::new();
+        assert!(sink.pull(10).is_empty());
+    }
+}
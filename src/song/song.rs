@@ -1,97 +1,158 @@
 
 use rodio::{self, Source};
-use failure::{Error, err_msg};
+use failure::Error;
 
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::io::BufReader;
-use std::time::Duration;
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
-use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
 
-use super::{TimeSpan, FloatWindow, Inspectable};
+use super::{TimeSpan, Sample};
 use super::controls::Controls;
-use super::sources::{
-    SmartSource,
-    BaseSource,
-    FloatWindowSource,
-    StoppableSource,
-    PausableSource
-};
-
-pub enum SongMsg {
-    Loaded,
-    Failed(Error)
+use super::output::{Output, Track};
+use configuration::config;
+use console::Logger;
+use errors::AppError;
+
+/// Fully decoded song, interleaved by channels
+pub struct DecodedSong {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<Sample>,
+}
+
+pub fn decode<P: AsRef<Path>>(path: P) -> Result<DecodedSong, Error> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(AppError::NotFound(path.to_path_buf()).into());
+    }
+
+    let file = File::open(path).map_err(|e| AppError::Open(path.to_path_buf(), e))?;
+    let decoder = rodio::Decoder::new(BufReader::new(file))
+        .map_err(|e| AppError::Decode(path.to_path_buf(), e))?;
+
+    Ok(DecodedSong {
+        channels: decoder.channels(),
+        sample_rate: decoder.sample_rate(),
+        samples: decoder.collect(),
+    })
+}
+
+impl DecodedSong {
+    /// Problems which don't prevent playing the song
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.samples.is_empty() {
+            warnings.push("Song has no samples".to_owned());
+        }
+        if self.channels == 0 || self.sample_rate == 0 {
+            warnings.push(format!("Song has {} channels at {} Hz", self.channels, self.sample_rate));
+        } else if self.samples.len() % self.channels as usize != 0 {
+            warnings.push("Last frame of the song is truncated".to_owned());
+        }
+        warnings
+    }
 }
 
 pub struct Song {
+    logger: Logger,
     controls: Arc<Controls>,
+    /// Samples of the current song once it's decoded, shared with the output
+    track: Arc<Mutex<Option<Track>>>,
 }
 
 impl Song {
-    pub fn new() -> Self {
-        Song { controls: Arc::new(Controls::new()) }
+    pub fn new(logger: Logger) -> Self {
+        Song { logger, controls: Arc::new(Controls::new()), track: Arc::new(Mutex::new(None)) }
     }
 
-    #[allow(deprecated)]
-    pub fn open<P: AsRef<Path>>(&self, path: P) -> Receiver<SongMsg> {
+    /// Decodes the song and keeps it connected to an output device on a
+    /// separate thread. The result is reported to the log and failures
+    /// to the error dialog
+    pub fn open<P: AsRef<Path>>(&self, path: P) {
         use std::thread;
 
         let path: PathBuf = path.as_ref().into();
         let controls = self.controls.clone();
-        let (tx, rx) = channel();
+        let mut logger = self.logger.clone();
+        let id = self.detach();
+        let shared = self.track.clone();
+        *shared.lock().unwrap() = None;
 
         thread::spawn(move || {
-            let th = move || -> Result<(), Error> {
-                ensure!(path.exists(), "File not found");
-
-                let file = File::open(path)?;
-                let decoder = rodio::Decoder::new(BufReader::new(file))?;
-
-                let sample_rate = decoder.sample_rate();
-                let channels = decoder.channels();
-                let samples = decoder.collect::<Vec<_>>();
-                let controls2 = controls.clone();
-
-                let source = BaseSource::new(channels, sample_rate, samples);
-                let source = FloatWindowSource::new(source);
-                let source = SmartSource::new(source, controls.clone());
-                let source = StoppableSource::new(source);
-                let source = PausableSource::new(source)
-                    .amplify(1.0)
-                    .periodic_access(Duration::from_millis(5), move |src| {
-                        src.inner_mut().stop(controls.stopped());
-                        src.inner_mut().pause(controls.paused());
-                        src.inner_mut().play(controls.time());
-                        src.set_factor(controls.volume());
-                    })
-                .periodic_access(Duration::from_millis(995), move |src| {
-                    controls2.set_progress(src.inner().inner().current_sec() as u32);
-                })
-                .convert_samples();
-
-                let endpoint = rodio::default_output_device()
-                    .ok_or(err_msg("Can't get endpoints list"))?;
-                Ok(rodio::play_raw(&endpoint, source))
+            let song = match decode(&path) {
+                Ok(song) => song,
+                Err(e) => return logger.report(&e)
             };
-
-            match th() {
-                Ok(_) => tx.send(SongMsg::Loaded).expect("Can't send signal"),
-                Err(e) => tx.send(SongMsg::Failed(e)).expect("Can't send signal")
+            for warning in song.warnings() {
+                logger.warn(format!("{}: {}", path.display(), warning));
+            }
+            logger.debug(format!("Decoded {} samples, {} channels at {} Hz",
+                                 song.samples.len(), song.channels, song.sample_rate));
+            logger.info("Song was loaded");
+
+            let track = Track {
+                channels: song.channels,
+                sample_rate: song.sample_rate,
+                samples: Arc::new(song.samples),
+            };
+            {
+                let mut shared = shared.lock().unwrap();
+                if controls.track() == id {
+                    *shared = Some(track.clone());
+                }
             }
+            Output::new(logger, controls, track, id).run();
         });
+    }
 
-        rx
+    /// Ends the sources of the previous song and stops its output thread.
+    /// Returns the track number of the next song
+    fn detach(&self) -> usize {
+        self.controls.next_connection();
+        self.controls.set_stopped(true);
+        self.controls.next_track()
+    }
+
+    /// Decoded samples for analysis, `None` while the song is decoding
+    #[inline]
+    pub fn track(&self) -> Option<Track> {
+        self.track.lock().unwrap().clone()
+    }
+
+    /// Moves the song to the device chosen in settings
+    #[inline]
+    pub fn reconnect(&self) {
+        self.controls.set_reconnect();
+    }
+
+    /// Name of the device the song plays on, `None` if playback is disabled
+    #[inline]
+    pub fn device(&self) -> Option<String> {
+        self.controls.device()
     }
 
     #[inline]
     pub fn play(&self, time: TimeSpan) {
+        let config = config();
+        self.controls.set_fades(config.player.fade_ms as usize, config.player.loop_crossfade_ms as usize);
         self.controls.set_time(time);
         self.controls.set_stopped(false);
         self.controls.set_paused(false);
     }
 
+    /// Plays the time span from its start, even if the cursor is inside it
+    #[inline]
+    pub fn seek(&self, time: TimeSpan) {
+        self.controls.set_restart(true);
+        self.play(time);
+    }
+
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        !self.controls.stopped() && !self.controls.paused()
+    }
+
     #[inline]
     pub fn stop(&self) {
         self.controls.set_stopped(true);
@@ -102,20 +163,28 @@ impl Song {
         self.controls.set_paused(true);
     }
 
+    /// Plays the time span again from its start when it ends
+    #[inline]
+    pub fn set_looping(&self, looping: bool) {
+        self.controls.set_looping(looping);
+    }
+
     #[inline]
     pub fn volume(&mut self, value: f32) {
         self.controls.set_volume(value);
     }
 
+    /// Seconds from the song start of the sample which is being heard
     #[inline]
-    pub fn progress(&self) -> u32 {
-        self.controls.progress()
+    pub fn position(&self) -> f64 {
+        self.controls.position(config().player.output_latency_ms as f64 / 1000.0)
     }
 }
 
 impl Drop for Song {
     #[inline]
     fn drop(&mut self) {
-        self.controls.stopped.store(true, Ordering::Relaxed);
+        self.controls.release();
+        self.detach();
     }
 }
@@ -5,6 +5,7 @@ use super::Sample;
 use rodio::Source;
 
 use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 
 // TODO(alex): Rename to something more appropriate
@@ -13,11 +14,12 @@ pub struct BaseSource {
     channels: u16,
     sample_rate: u32,
     duration: Duration,
-    source: Vec<Sample> // TODO(alex): abstract over samples type
+    source: Arc<Vec<Sample>> // TODO(alex): abstract over samples type
 }
 
 impl BaseSource {
-    pub fn new(channels: u16, sample_rate: u32, source: Vec<Sample>) -> Self {
+    /// Samples are shared, so the song can be connected to another device without copying them
+    pub fn new(channels: u16, sample_rate: u32, source: Arc<Vec<Sample>>) -> Self {
         let duration_ns = 1_000_000_000u64.checked_mul(source.len() as u64).unwrap() /
             sample_rate as u64 / channels as u64;
         let duration = Duration::new(duration_ns / 1_000_000_000,
@@ -79,6 +81,10 @@ impl DirectAccess for BaseSource {
     fn set_current(&mut self, index: usize) {
         self.current = index;
     }
+    #[inline]
+    fn get(&self, index: usize) -> Option<Self::Item> {
+        self.source.get(index).cloned()
+    }
 }
 
 impl fmt::Debug for BaseSource {
@@ -0,0 +1,121 @@
+
+use rodio::{Sample as Sample_, Source};
+
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+/// Gain envelope which ramps the source in and out over `length` samples,
+/// so state changes of the inner sources are applied while it's silent
+pub struct FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    source: T,
+    gain: f32,
+    target: f32,
+    length: usize
+}
+
+impl<T> FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    /// Starts silent
+    #[inline]
+    pub fn new(source: T) -> Self {
+        FadeSource { source, gain: 0.0, target: 0.0, length: 0 }
+    }
+
+    /// Length of a fade in samples of all channels, zero switches instantly
+    #[inline]
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length;
+    }
+
+    #[inline]
+    pub fn fade_in(&mut self) {
+        self.target = 1.0;
+    }
+
+    #[inline]
+    pub fn fade_out(&mut self) {
+        self.target = 0.0;
+    }
+
+    #[inline]
+    pub fn is_silent(&self) -> bool {
+        self.gain == 0.0
+    }
+}
+
+impl<T> Source for FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<T> Iterator for FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    type Item = <T as Iterator>::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.source.next()?;
+        if self.gain != self.target {
+            let step = if self.length == 0 { 1.0 } else { 1.0 / self.length as f32 };
+            self.gain = if self.gain < self.target {
+                (self.gain + step).min(self.target)
+            } else {
+                (self.gain - step).max(self.target)
+            };
+        }
+
+        if self.gain == 1.0 {
+            Some(sample)
+        } else {
+            Some(sample.amplify(self.gain))
+        }
+    }
+}
+
+impl<T> Deref for FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.source
+    }
+}
+
+impl<T> DerefMut for FadeSource<T>
+    where T: Source,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.source
+    }
+}
@@ -4,6 +4,7 @@ mod smart;
 mod windowed;
 mod stoppable;
 mod pausable;
+mod fade;
 
 use super::*;
 
@@ -12,5 +13,6 @@ pub use self::smart::SmartSource;
 pub use self::windowed::FloatWindowSource;
 pub use self::stoppable::StoppableSource;
 pub use self::pausable::PausableSource;
+pub use self::fade::FadeSource;
 
 pub type Sample = i16;
@@ -1,6 +1,9 @@
 
+use super::{FloatWindow, Resettable, TimeSpan};
+
 use rodio::{Sample as Sample_, Source};
 
+use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
@@ -28,6 +31,48 @@ impl<T> PausableSource<T>
 
 }
 
+impl<T> Resettable for PausableSource<T>
+    where T: Resettable,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.source.reset();
+    }
+}
+
+impl<T> FloatWindow for PausableSource<T>
+    where T: FloatWindow,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn play(&mut self, time: TimeSpan) {
+        self.source.play(time);
+    }
+
+    #[inline]
+    fn end(&self) -> usize {
+        self.source.end()
+    }
+
+    #[inline]
+    fn cursor(&self) -> usize {
+        self.source.cursor()
+    }
+}
+
+impl<T> fmt::Debug for PausableSource<T>
+    where T: Source + fmt::Debug,
+          <T as Iterator>::Item: Sample_
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PausableSource")
+            .field("source", &self.source)
+            .field("paused", &self.paused)
+            .finish()
+    }
+}
+
 impl<T> Source for PausableSource<T>
     where T: Source,
           <T as Iterator>::Item: Sample_
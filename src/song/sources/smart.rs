@@ -14,18 +14,23 @@ pub struct SmartSource<T>
           <T as Iterator>::Item: Sample_
 {
     source: T,
-    controls: Arc<Controls>
+    controls: Arc<Controls>,
+    connection: usize
 }
 
 impl<T> SmartSource<T> 
     where T: FloatWindow,
           <T as Iterator>::Item: Sample_
 {
+    /// The source ends once the song is connected to another device, another
+    /// song is opened or the song is dropped. The mixer drops ended sources.
+    /// It wraps the stoppable and pausable sources, so it ends while stopped too
     #[inline]
-    pub fn new(source: T, controls: Arc<Controls>) -> Self {
+    pub fn new(source: T, controls: Arc<Controls>, connection: usize) -> Self {
         SmartSource {
             source,
             controls,
+            connection,
         }
     }
 }
@@ -49,7 +54,11 @@ impl<T> Iterator for SmartSource<T>
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        if self.controls.is_detached(self.connection) {
+            return None;
+        }
         if let a @ Some(_) = self.source.next() {
+            self.controls.set_cursor(self.source.cursor());
             a
         } else {
             self.controls.set_stopped(true);
@@ -1,8 +1,9 @@
 
-use super::Resettable;
+use super::{FloatWindow, Resettable, TimeSpan};
 
 use rodio::{Sample as Sample_, Source};
 
+use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
@@ -30,6 +31,48 @@ impl<T> StoppableSource<T>
 
 }
 
+impl<T> Resettable for StoppableSource<T>
+    where T: Resettable,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.source.reset();
+    }
+}
+
+impl<T> FloatWindow for StoppableSource<T>
+    where T: FloatWindow,
+          <T as Iterator>::Item: Sample_
+{
+    #[inline]
+    fn play(&mut self, time: TimeSpan) {
+        self.source.play(time);
+    }
+
+    #[inline]
+    fn end(&self) -> usize {
+        self.source.end()
+    }
+
+    #[inline]
+    fn cursor(&self) -> usize {
+        self.source.cursor()
+    }
+}
+
+impl<T> fmt::Debug for StoppableSource<T>
+    where T: Resettable,
+          <T as Iterator>::Item: Sample_
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoppableSource")
+            .field("source", &self.source)
+            .field("stopped", &self.stopped)
+            .finish()
+    }
+}
+
 impl<T> Source for StoppableSource<T>
     where T: Resettable,
           <T as Iterator>::Item: Sample_
@@ -13,6 +13,11 @@ pub struct FloatWindowSource<T>
 {
     start: usize,
     end: usize,
+    /// Fade at the window edges in samples of all channels
+    fade: usize,
+    /// Crossfade of the window end into its start when looping
+    crossfade: usize,
+    looping: bool,
     source: T
 }
 
@@ -25,10 +30,44 @@ impl<T> FloatWindowSource<T>
         FloatWindowSource {
             source,
             start: 0,
-            end: 0
+            end: 0,
+            fade: 0,
+            crossfade: 0,
+            looping: false
         }
     }
 
+    /// Lengths are in samples of all channels
+    #[inline]
+    pub fn set_fades(&mut self, fade: usize, crossfade: usize) {
+        self.fade = fade;
+        self.crossfade = crossfade;
+    }
+
+    #[inline]
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// At most half of the window and aligned to whole frames, so channels
+    /// of the window start and end match
+    #[inline]
+    fn crossfade_len(&self) -> usize {
+        let channels = self.source.channels().max(1) as usize;
+        let len = self.crossfade.min((self.end - self.start) / 2);
+        len - len % channels
+    }
+
+    #[inline]
+    fn edge_gain(&self, current: usize) -> f32 {
+        if self.fade == 0 {
+            return 1.0;
+        }
+        let from_start = current.saturating_sub(self.start);
+        let to_end = self.end.saturating_sub(current);
+        (from_start.min(to_end) as f32 / self.fade as f32).min(1.0)
+    }
+
     #[inline]
     fn sample_rate_for_all_channels(&self) -> usize {
         self.source.channels() as usize * self.source.sample_rate() as usize
@@ -113,13 +152,31 @@ impl<T> Iterator for FloatWindowSource<T>
 {
     type Item = <T as Iterator>::Item;
 
-    #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current() < self.end {
-            self.source.next()
-        } else {
-            None
+        let crossfade = if self.looping { self.crossfade_len() } else { 0 };
+        if self.current() >= self.end {
+            if !self.looping || self.end <= self.start {
+                return None;
+            }
+            // the start of the window was already mixed into its end
+            self.source.set_current(self.start + crossfade);
         }
+
+        let current = self.current();
+        let sample = self.source.next()?;
+        let to_end = self.end - current;
+        if crossfade > 0 && to_end <= crossfade {
+            let head = self.source.get(self.start + crossfade - to_end)
+                .unwrap_or(<T as Iterator>::Item::zero_value());
+            let t = (crossfade - to_end) as f32 / crossfade as f32;
+            return Some(sample.amplify(1.0 - t).saturating_add(head.amplify(t)));
+        }
+        if crossfade > 0 {
+            return Some(sample);
+        }
+
+        let gain = self.edge_gain(current);
+        if gain < 1.0 { Some(sample.amplify(gain)) } else { Some(sample) }
     }
 
     #[inline]
@@ -2,15 +2,23 @@
 use imgui::*;
 
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 
 use song::TimeSpan;
-use configuration::CONFIG;
+use configuration::config;
+use language;
 
 #[derive(Serialize, Deserialize)]
 pub struct AppData {
     pub lyrics: Vec<LanguageTab>,
     pub timings: Vec<TimeFrame>,
-    pub path: String
+    pub path: String,
+    /// Added to every time frame on export, in milliseconds
+    #[serde(default)]
+    pub offset_ms: i32,
+    /// Repeated parts of the song shared by several frames
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -18,8 +26,132 @@ pub struct TimeFrame {
     pub start: f32,
     pub end: f32,
     pub tooltip: Option<String>,
+    /// Index of the lyrics line (in every language tab) this frame belongs to
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// Karaoke timings of the frame's words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordTiming>,
+    /// Name shown in the frame list and above the karaoke line
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    #[serde(default)]
+    pub section: SectionKind,
+    /// Overrides the color of the section kind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Name of the shared section the frame is an occurrence of,
+    /// its text is used instead of the lyrics line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared: Option<String>,
     #[serde(skip)]
-    pub remove: bool
+    pub remove: bool,
+    /// Target of bulk operations
+    #[serde(skip)]
+    pub selected: bool
+}
+
+/// Lyrics sung several times, like a chorus. Every linked frame shows the
+/// same text, so it's edited once per language
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    /// Text by language tag
+    #[serde(default)]
+    pub texts: BTreeMap<String, String>
+}
+
+impl Section {
+    pub fn new<T: ToString>(name: T) -> Self {
+        Section {
+            name: name.to_string(),
+            texts: BTreeMap::new()
+        }
+    }
+
+    pub fn text(&self, lang: &str) -> &str {
+        self.texts.get(lang).map(|t| t.as_str()).unwrap_or("")
+    }
+
+    pub fn set_text(&mut self, lang: &str, text: &str) {
+        if text.is_empty() {
+            self.texts.remove(lang);
+        } else {
+            self.texts.insert(lang.to_owned(), text.to_owned());
+        }
+    }
+
+    /// Keeps the texts of a language tab whose tag was changed
+    pub fn rename_lang(&mut self, old: &str, new: &str) {
+        if let Some(text) = self.texts.remove(old) {
+            self.texts.insert(new.to_owned(), text);
+        }
+    }
+}
+
+/// Part of the song a frame belongs to
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SectionKind {
+    None,
+    Intro,
+    Verse,
+    PreChorus,
+    Chorus,
+    Bridge,
+    Outro
+}
+
+pub const SECTION_KINDS: &[SectionKind] = &[
+    SectionKind::None,
+    SectionKind::Intro,
+    SectionKind::Verse,
+    SectionKind::PreChorus,
+    SectionKind::Chorus,
+    SectionKind::Bridge,
+    SectionKind::Outro,
+];
+
+impl Default for SectionKind {
+    fn default() -> Self {
+        SectionKind::None
+    }
+}
+
+impl SectionKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SectionKind::None => "none",
+            SectionKind::Intro => "intro",
+            SectionKind::Verse => "verse",
+            SectionKind::PreChorus => "pre-chorus",
+            SectionKind::Chorus => "chorus",
+            SectionKind::Bridge => "bridge",
+            SectionKind::Outro => "outro"
+        }
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        match *self {
+            SectionKind::None => [0.7, 0.7, 0.7],
+            SectionKind::Intro | SectionKind::Outro => [0.6, 0.6, 0.9],
+            SectionKind::Verse => [0.5, 0.8, 0.5],
+            SectionKind::PreChorus => [0.9, 0.8, 0.4],
+            SectionKind::Chorus => [0.95, 0.5, 0.4],
+            SectionKind::Bridge => [0.8, 0.5, 0.9]
+        }
+    }
+}
+
+/// Word (or syllable) position in seconds from the song start
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct WordTiming {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub syllables: Vec<WordTiming>,
 }
 
 impl<T: Borrow<TimeFrame>> From<T> for TimeSpan {
@@ -38,34 +170,108 @@ impl<T: Borrow<TimeFrame>> From<T> for TimeSpan {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LanguageTab {
-    lang: String,
-    text: String
+    /// BCP-47 language tag
+    pub lang: String,
+    pub text: String,
+    /// Display name, empty in projects saved before it was introduced
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub role: Role,
+    /// Text direction, missing in projects saved before it was introduced
+    #[serde(default)]
+    pub rtl: Option<bool>,
+    /// Subtitles style, defaults depend on the tab role (see `SubtitleStyle::for_role`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<SubtitleStyle>
 }
 
 pub struct ImLanguageTab {
     pub lang: ImString,
-    pub text: ImString
+    pub name: ImString,
+    pub text: ImString,
+    pub role: Role,
+    pub rtl: bool,
+    pub style: Option<SubtitleStyle>
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    Original,
+    Translation
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Translation
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Position {
+    Top,
+    Bottom
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubtitleStyle {
+    pub font: String,
+    pub size: u32,
+    pub color: [f32; 3],
+    pub position: Position
+}
+
+impl SubtitleStyle {
+    /// The original goes to the bottom, translations to the top
+    pub fn for_role(role: Role) -> Self {
+        SubtitleStyle {
+            font: config().export.font.clone(),
+            size: config().export.font_size,
+            color: if role == Role::Original { [1.0, 1.0, 1.0] } else { [1.0, 1.0, 0.6] },
+            position: if role == Role::Original { Position::Bottom } else { Position::Top }
+        }
+    }
+}
+
+impl LanguageTab {
+    pub fn style(&self) -> SubtitleStyle {
+        self.style.clone().unwrap_or_else(|| SubtitleStyle::for_role(self.role))
+    }
+
+    /// Direction saved in the project, guessed from the tag if it's missing
+    pub fn is_rtl(&self) -> bool {
+        self.rtl.unwrap_or_else(|| language::is_rtl(&self.lang))
+    }
 }
 
 impl<'a> From<&'a ImLanguageTab> for LanguageTab {
     fn from(tab: &'a ImLanguageTab) -> Self {
+        let lang = tab.lang.to_str();
         LanguageTab {
-            lang: tab.lang.to_str().to_owned(),
+            lang: language::canonical_tag(lang).unwrap_or_else(|_| lang.to_owned()),
             text: tab.text.to_str().to_owned(),
+            name: tab.name.to_str().to_owned(),
+            role: tab.role,
+            rtl: Some(tab.rtl),
+            style: tab.style.clone(),
         }
     }
 }
 
 impl From<LanguageTab> for ImLanguageTab {
     fn from(tab: LanguageTab) -> Self {
-        let mut text = ImString::with_capacity(CONFIG.state.default_lyrics_text_size);
-        text.push_str(&tab.text);
-        ImLanguageTab {
-            lang: ImString::new(tab.lang),
-            text
+        let mut result = ImLanguageTab::new(&tab.lang, &tab.text);
+        // Projects saved before language metadata was introduced have no name
+        if !tab.name.is_empty() {
+            result.name.clear();
+            result.name.push_str(&tab.name);
         }
+        result.rtl = tab.is_rtl();
+        result.role = tab.role;
+        result.style = tab.style;
+        result
     }
 }
 
@@ -74,18 +280,52 @@ impl ImLanguageTab {
         where L: AsRef<str>,
               T: AsRef<str>
     {
-        let mut t = ImString::with_capacity(CONFIG.state.default_lyrics_text_size);
+        let mut t = ImString::with_capacity(config().state.default_lyrics_text_size);
         t.push_str(text.as_ref());
+        let mut l = ImString::with_capacity(config().main_window.lang_name_len);
+        l.push_str(lang.as_ref());
+        let mut name = ImString::with_capacity(config().main_window.lang_name_len);
+        name.push_str(&language::display_name(lang.as_ref()));
         ImLanguageTab {
-            lang: ImString::new(lang.as_ref()),
-            text: t
+            lang: l,
+            name,
+            text: t,
+            role: Role::Translation,
+            rtl: language::is_rtl(lang.as_ref()),
+            style: None
         }
     }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        language::canonical_tag(self.lang.to_str()).is_ok()
+    }
+
+    /// Line of the tab's text, indexed the same way as `TimeFrame::line`
+    pub fn line(&self, index: usize) -> Option<&str> {
+        self.text.to_str().lines().nth(index)
+    }
+
+    /// Replaces a single line, appending empty lines if the text is shorter
+    pub fn set_line(&mut self, index: usize, line: &str) {
+        let text = {
+            let mut lines = self.text.to_str().lines().collect::<Vec<_>>();
+            while lines.len() <= index {
+                lines.push("");
+            }
+            lines[index] = line;
+            lines.join("\n")
+        };
+        self.text.clear();
+        self.text.push_str(&text);
+    }
 }
 
 impl Default for ImLanguageTab {
     fn default() -> Self {
-        ImLanguageTab::new(&CONFIG.state.default_tab_lang, "")
+        let mut tab = ImLanguageTab::new(&config().state.default_tab_lang, "");
+        tab.role = Role::Original;
+        tab
     }
 }
 
@@ -99,4 +339,102 @@ impl TimeFrame {
     pub fn new() -> Self {
         TimeFrame::default()
     }
+
+    pub fn for_line(line: usize, start: f64, end: f64) -> Self {
+        TimeFrame {
+            start: from_secs(start),
+            end: from_secs(end),
+            line: Some(line),
+            .. Default::default()
+        }
+    }
+
+    /// Own color or the color of the section kind
+    pub fn color(&self) -> [f32; 3] {
+        self.color.unwrap_or_else(|| self.section.color())
+    }
+
+    /// Label, the shared section or the section kind name if there is no label
+    pub fn title(&self) -> &str {
+        match self.shared {
+            _ if !self.label.is_empty() => &self.label,
+            Some(ref name) => name,
+            None => self.section.name()
+        }
+    }
+
+    /// Text of the frame in a language: the shared section text or the line of the tab text
+    pub fn text<'a>(&self, lang: &str, lyrics: &'a str, sections: &'a [Section]) -> Option<&'a str> {
+        match self.shared {
+            Some(ref name) => sections.iter()
+                .find(|s| s.name == *name)
+                .map(|s| s.text(lang))
+                .filter(|t| !t.is_empty()),
+            None => self.line.and_then(|l| lyrics.lines().nth(l))
+        }
+    }
+
+    #[inline]
+    pub fn start_secs(&self) -> f64 {
+        to_secs(self.start)
+    }
+
+    #[inline]
+    pub fn end_secs(&self) -> f64 {
+        to_secs(self.end)
+    }
+
+    /// Moves the frame and its word timings. A shift before the song start
+    /// is limited, so the frame starts at 0:00 and keeps its length
+    pub fn shift(&mut self, secs: f64) {
+        let secs = secs.max(-self.start_secs());
+        self.start = from_secs(self.start_secs() + secs);
+        self.end = from_secs(self.end_secs() + secs);
+        for word in &mut self.words {
+            word.shift(secs);
+        }
+    }
+
+    /// Stretches the frame and its word timings away from `anchor` in seconds
+    pub fn scale(&mut self, anchor: f64, factor: f64) {
+        self.start = from_secs(anchor + (self.start_secs() - anchor) * factor);
+        self.end = from_secs(anchor + (self.end_secs() - anchor) * factor);
+        for word in &mut self.words {
+            word.scale(anchor, factor);
+        }
+    }
 }
+
+impl WordTiming {
+    pub fn shift(&mut self, secs: f64) {
+        self.start = (self.start + secs).max(0.0);
+        self.end = (self.end + secs).max(0.0);
+        for syllable in &mut self.syllables {
+            syllable.shift(secs);
+        }
+    }
+
+    pub fn scale(&mut self, anchor: f64, factor: f64) {
+        self.start = (anchor + (self.start - anchor) * factor).max(0.0);
+        self.end = (anchor + (self.end - anchor) * factor).max(0.0);
+        for syllable in &mut self.syllables {
+            syllable.scale(anchor, factor);
+        }
+    }
+}
+
+/// Converts `minutes.seconds` notation used by time frames (1.30 is 1:30)
+/// into seconds. Fractional seconds are kept (1.305 is 1:30.5)
+pub fn to_secs(time: f32) -> f64 {
+    let time = time as f64;
+    let minutes = time.trunc();
+    minutes * 60.0 + (time - minutes) * 100.0
+}
+
+/// Inverse of `to_secs`
+pub fn from_secs(secs: f64) -> f32 {
+    let secs = secs.max(0.0);
+    let minutes = (secs / 60.0).trunc();
+    (minutes + (secs - minutes * 60.0) / 100.0) as f32
+}
+
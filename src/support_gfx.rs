@@ -1,10 +1,15 @@
 
 use glutin;
-use imgui::{FontGlyphRange, ImFontConfig, ImGui, ImGuiMouseCursor, Ui};
+use imgui::{ImGui, ImGuiMouseCursor, ImString, Ui};
 use imgui_gfx_renderer::{Renderer, Shaders};
 
 use std::time::Instant;
 
+use fonts;
+use session;
+use keybindings::KeyChord;
+use console::Logger;
+
 #[derive(Copy, Clone, PartialEq, Debug, Default)]
 struct MouseState {
     pos: (i32, i32),
@@ -16,9 +21,13 @@ const CLEAR_COLOR: [f32; 4] = [114.0 / 255.0, 144.0 / 255.0, 154.0 / 255.0, 1.0]
 
 pub trait AppContext {
     fn show<'a>(&mut self, ui: &Ui<'a>) -> bool;
+
+    /// Called for every key press before the next frame is shown.
+    /// `text_input` is set when a text field has keyboard focus
+    fn key_pressed(&mut self, _key: KeyChord, _text_input: bool) {}
 }
 
-pub fn run<T: AppContext>(title: &'static str, mut app: T) {
+pub fn run<T: AppContext>(title: &'static str, mut logger: Logger, mut app: T) {
     use gfx::{self, Device};
     use gfx_window_glutin;
     use glutin::GlContext;
@@ -52,9 +61,10 @@ pub fn run<T: AppContext>(title: &'static str, mut app: T) {
     };
 
     let mut imgui = ImGui::init();
-    let config = ImFontConfig::new().oversample_h(1).pixel_snap_h(true).size_pixels(15.0);
-    config.rasterizer_multiply(1.0)
-        .add_font(&mut imgui.fonts(), include_bytes!("../resources/tahoma.ttf"), &FontGlyphRange::cyrillic());
+    if let Some(path) = session::layout_file() {
+        imgui.set_ini_filename(path.to_str().map(ImString::new));
+    }
+    fonts::add_fonts(&mut imgui, &mut logger);
     let mut renderer = Renderer::init(&mut imgui, &mut factory, shaders, main_color.clone())
         .expect("Failed to initialize renderer");
 
@@ -63,6 +73,8 @@ pub fn run<T: AppContext>(title: &'static str, mut app: T) {
     let mut last_frame = Instant::now();
     let mut mouse_state = MouseState::default();
     let mut quit = false;
+    let mut pressed_keys = Vec::new();
+    let mut text_input = false;
 
     loop {
         events_loop.poll_events(|event| {
@@ -77,7 +89,12 @@ pub fn run<T: AppContext>(title: &'static str, mut app: T) {
                         renderer.update_render_target(main_color.clone());
                     }
                     Closed => quit = true,
-                    KeyboardInput { input, .. } => configure_imgui_keys(&mut imgui, input),
+                    KeyboardInput { input, .. } => {
+                        if input.state == Pressed {
+                            pressed_keys.extend(KeyChord::from_input(&input));
+                        }
+                        configure_imgui_keys(&mut imgui, input);
+                    }
                     CursorMoved { position: (x, y), .. } => mouse_state.pos = (x as i32, y as i32),
                     MouseInput { state, button, .. } => {
                         match button {
@@ -134,10 +151,15 @@ pub fn run<T: AppContext>(title: &'static str, mut app: T) {
             (size_pixels.1 as f32 / hdipi) as u32,
         );
 
+        for key in pressed_keys.drain(..) {
+            app.key_pressed(key, text_input);
+        }
+
         let ui = imgui.frame(size_points, size_pixels, delta_s);
         if !app.show(&ui) {
             break;
         }
+        text_input = ui.want_capture_keyboard();
 
         encoder.clear(&main_color, CLEAR_COLOR);
         renderer.render(ui, &mut factory, &mut encoder).expect("Rendering failed");
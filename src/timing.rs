@@ -0,0 +1,317 @@
+
+use std::cmp::Ordering;
+
+use state::{TimeFrame, from_secs};
+
+/// Indices of the selected frames, of all frames if none is selected
+pub fn targets(frames: &[TimeFrame]) -> Vec<usize> {
+    let selected = frames.iter()
+        .enumerate()
+        .filter(|&(_, f)| f.selected)
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    if selected.is_empty() { (0..frames.len()).collect() } else { selected }
+}
+
+/// Shifts the frames together, so a shift before the song start is limited
+/// to the earliest of them. Returns the shift applied in seconds
+pub fn shift(frames: &mut [TimeFrame], secs: f64) -> f64 {
+    let targets = targets(frames);
+    let earliest = targets.iter()
+        .map(|&idx| frames[idx].start_secs())
+        .fold(None, |min: Option<f64>, s| Some(min.map_or(s, |m| m.min(s))));
+    let secs = earliest.map_or(secs, |earliest| secs.max(-earliest));
+    for idx in targets {
+        frames[idx].shift(secs);
+    }
+    secs
+}
+
+/// Scales the frames from the start of the first one, to retime a song
+/// from a recording with another tempo
+pub fn scale(frames: &mut [TimeFrame], factor: f64) {
+    let targets = targets(frames);
+    let anchor = targets.iter()
+        .map(|&idx| frames[idx].start_secs())
+        .fold(None, |min: Option<f64>, s| Some(min.map_or(s, |m| m.min(s))));
+    if let Some(anchor) = anchor {
+        for idx in targets {
+            frames[idx].scale(anchor, factor);
+        }
+    }
+}
+
+pub fn sort(frames: &mut Vec<TimeFrame>) {
+    frames.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(Ordering::Equal));
+}
+
+/// Splits the frame under the playhead in two, words go to the half they start in.
+/// Returns whether a frame was split
+pub fn split(frames: &mut Vec<TimeFrame>, position: f64) -> bool {
+    let idx = match targets(frames).into_iter()
+        .find(|&idx| frames[idx].start_secs() < position && position < frames[idx].end_secs())
+    {
+        Some(idx) => idx,
+        None => return false
+    };
+
+    let mut second = frames[idx].clone();
+    {
+        let first = &mut frames[idx];
+        first.end = from_secs(position);
+        first.words.retain(|w| w.start < position);
+    }
+    second.start = from_secs(position);
+    second.words.retain(|w| w.start >= position);
+    frames.insert(idx + 1, second);
+    true
+}
+
+/// Replaces the selected frames with one spanning all of them. Frames are merged
+/// only if they are adjacent in time, with no unselected frame between them,
+/// and linked to the same lyrics line or section (or to nothing), a frame
+/// can't show the lyrics of several links
+pub fn merge(frames: &mut Vec<TimeFrame>) -> Result<(), &'static str> {
+    let mut by_start = (0..frames.len()).collect::<Vec<_>>();
+    by_start.sort_by(|&a, &b| frames[a].start.partial_cmp(&frames[b].start).unwrap_or(Ordering::Equal));
+    let first = by_start.iter().position(|&idx| frames[idx].selected);
+    let last = by_start.iter().rposition(|&idx| frames[idx].selected);
+    let selected = match (first, last) {
+        (Some(first), Some(last)) if last > first => by_start[first..last + 1].to_vec(),
+        _ => return Err("Select at least two frames to merge")
+    };
+    if selected.iter().any(|&idx| !frames[idx].selected) {
+        return Err("Only adjacent frames can be merged, unselected frames are between them");
+    }
+    {
+        let mut links = selected.iter()
+            .map(|&idx| (frames[idx].line, frames[idx].shared.as_ref()))
+            .filter(|&(line, ref shared)| line.is_some() || shared.is_some());
+        if let Some(link) = links.next() {
+            if links.any(|other| other != link) {
+                return Err("Frames linked to different lyrics lines or sections can't be merged");
+            }
+        }
+    }
+
+    let mut merged: Option<TimeFrame> = None;
+    for frame in selected.iter().map(|&idx| &frames[idx]) {
+        match merged {
+            Some(ref mut merged) => {
+                if frame.end_secs() > merged.end_secs() {
+                    merged.end = frame.end;
+                }
+                merged.words.extend(frame.words.iter().cloned());
+                merged.line = merged.line.or(frame.line);
+                if merged.shared.is_none() {
+                    merged.shared = frame.shared.clone();
+                }
+                if merged.tooltip.is_none() {
+                    merged.tooltip = frame.tooltip.clone();
+                }
+            }
+            None => merged = Some(frame.clone())
+        }
+    }
+
+    let position = frames.iter().position(|f| f.selected).unwrap_or(0);
+    frames.retain(|f| !f.selected);
+    if let Some(mut merged) = merged {
+        merged.words.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(Ordering::Equal));
+        frames.insert(position, merged);
+    }
+    Ok(())
+}
+
+/// Extends the selected frames (all of them if none is selected) up to
+/// the start of the next frame
+pub fn close_gaps(frames: &mut Vec<TimeFrame>) {
+    sort(frames);
+    for idx in targets(frames) {
+        let next_start = match frames.get(idx + 1) {
+            Some(next) => next.start,
+            None => continue
+        };
+        if frames[idx].end < next_start {
+            frames[idx].end = next_start;
+        }
+    }
+}
+
+/// Copies the frames shifted by `offset` seconds, with their lyrics line, shared
+/// section and word timings. Copies mostly covered by an existing frame are
+/// skipped. Returns the number of added frames
+pub fn clone_shifted(frames: &mut Vec<TimeFrame>, indices: &[usize], offset: f64) -> usize {
+    let mut added = 0;
+    for &idx in indices {
+        let mut copy = frames[idx].clone();
+        copy.shift(offset);
+        copy.selected = false;
+
+        let (start, end) = (copy.start_secs(), copy.end_secs());
+        let covered = frames.iter()
+            .any(|f| (f.end_secs().min(end) - f.start_secs().max(start)) * 2.0 > end - start);
+        if !covered {
+            frames.push(copy);
+            added += 1;
+        }
+    }
+    sort(frames);
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state::WordTiming;
+
+    fn frame(start: f64, end: f64) -> TimeFrame {
+        TimeFrame { start: from_secs(start), end: from_secs(end), .. Default::default() }
+    }
+
+    fn selected(start: f64, end: f64) -> TimeFrame {
+        TimeFrame { selected: true, .. frame(start, end) }
+    }
+
+    fn word(start: f64) -> WordTiming {
+        WordTiming { text: "la".into(), start, end: start + 0.5, syllables: Vec::new() }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn times(frames: &[TimeFrame]) -> Vec<(f64, f64)> {
+        frames.iter()
+            .map(|f| ((f.start_secs() * 100.0).round() / 100.0, (f.end_secs() * 100.0).round() / 100.0))
+            .collect()
+    }
+
+    #[test]
+    fn shift_moves_only_selected_frames() {
+        let mut frames = vec![frame(1.0, 2.0), selected(3.0, 4.0)];
+        assert!(close(shift(&mut frames, 1.5), 1.5));
+        assert_eq!(times(&frames), vec![(1.0, 2.0), (4.5, 5.5)]);
+    }
+
+    #[test]
+    fn shift_before_song_start_is_limited_to_the_earliest_frame() {
+        let mut frames = vec![frame(2.0, 3.0), frame(5.0, 6.0)];
+        assert!(close(shift(&mut frames, -4.0), -2.0));
+        assert_eq!(times(&frames), vec![(0.0, 1.0), (3.0, 4.0)]);
+    }
+
+    #[test]
+    fn frame_shift_keeps_its_length() {
+        let mut frame = frame(2.0, 3.0);
+        frame.words.push(word(2.0));
+        frame.shift(-10.0);
+        assert_eq!(times(&[frame.clone()]), vec![(0.0, 1.0)]);
+        assert!(close(frame.words[0].start, 0.0));
+    }
+
+    #[test]
+    fn scale_stretches_from_the_first_start() {
+        let mut frames = vec![frame(10.0, 12.0), frame(14.0, 16.0)];
+        scale(&mut frames, 0.5);
+        assert_eq!(times(&frames), vec![(10.0, 11.0), (12.0, 13.0)]);
+    }
+
+    #[test]
+    fn split_divides_words_by_their_start() {
+        let mut first = frame(0.0, 4.0);
+        first.words = vec![word(1.0), word(3.0)];
+        let mut frames = vec![first];
+
+        assert!(!split(&mut frames, 5.0));
+        assert!(split(&mut frames, 2.0));
+        assert_eq!(times(&frames), vec![(0.0, 2.0), (2.0, 4.0)]);
+        assert_eq!(frames[0].words[0].start, 1.0);
+        assert_eq!(frames[1].words[0].start, 3.0);
+    }
+
+    #[test]
+    fn merge_spans_adjacent_selected_frames() {
+        let mut frames = vec![selected(0.0, 1.0), selected(1.5, 3.0), frame(4.0, 5.0)];
+        frames[1].words.push(word(2.0));
+        frames[0].words.push(word(0.5));
+        assert!(merge(&mut frames).is_ok());
+        assert_eq!(times(&frames), vec![(0.0, 3.0), (4.0, 5.0)]);
+        assert_eq!(frames[0].words.len(), 2);
+        assert!(frames[0].words[0].start < frames[0].words[1].start);
+    }
+
+    #[test]
+    fn merge_refuses_frames_with_unselected_ones_between() {
+        // adjacency follows the start times, not the order in the list
+        let mut frames = vec![selected(0.0, 1.0), selected(4.0, 5.0), frame(2.0, 3.0)];
+        assert!(merge(&mut frames).is_err());
+        assert_eq!(frames.len(), 3);
+    }
+
+    #[test]
+    fn merge_needs_two_selected_frames() {
+        let mut frames = vec![selected(0.0, 1.0), frame(2.0, 3.0)];
+        assert!(merge(&mut frames).is_err());
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn merge_refuses_frames_of_different_lines_or_sections() {
+        let mut frames = vec![selected(0.0, 1.0), selected(1.0, 2.0)];
+        frames[0].line = Some(0);
+        frames[1].line = Some(1);
+        assert!(merge(&mut frames).is_err());
+
+        frames[1].line = None;
+        frames[1].shared = Some("chorus".into());
+        assert!(merge(&mut frames).is_err());
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn merge_keeps_the_link_of_linked_frames() {
+        let mut frames = vec![selected(0.0, 1.0), selected(1.0, 2.0), selected(2.0, 3.0)];
+        frames[1].shared = Some("chorus".into());
+        frames[2].shared = Some("chorus".into());
+        assert!(merge(&mut frames).is_ok());
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].shared, Some("chorus".into()));
+    }
+
+    #[test]
+    fn close_gaps_extends_frames_to_the_next_one() {
+        let mut frames = vec![frame(5.0, 6.0), frame(0.0, 1.0), frame(2.0, 3.0)];
+        close_gaps(&mut frames);
+        assert_eq!(times(&frames), vec![(0.0, 2.0), (2.0, 5.0), (5.0, 6.0)]);
+    }
+
+    #[test]
+    fn close_gaps_extends_only_selected_frames() {
+        let mut frames = vec![selected(0.0, 1.0), frame(2.0, 3.0), frame(5.0, 6.0)];
+        close_gaps(&mut frames);
+        assert_eq!(times(&frames), vec![(0.0, 2.0), (2.0, 3.0), (5.0, 6.0)]);
+    }
+
+    #[test]
+    fn close_gaps_doesnt_shorten_overlapping_frames() {
+        let mut frames = vec![frame(0.0, 3.0), frame(2.0, 4.0)];
+        close_gaps(&mut frames);
+        assert_eq!(times(&frames), vec![(0.0, 3.0), (2.0, 4.0)]);
+    }
+
+    #[test]
+    fn clone_shifted_skips_covered_copies() {
+        let mut original = selected(0.0, 2.0);
+        original.line = Some(3);
+        let mut frames = vec![frame(20.0, 22.0), original];
+
+        assert_eq!(clone_shifted(&mut frames, &[1], 10.0), 1);
+        assert_eq!(times(&frames), vec![(0.0, 2.0), (10.0, 12.0), (20.0, 22.0)]);
+        assert_eq!(frames[1].line, Some(3));
+        assert!(!frames[1].selected);
+
+        assert_eq!(clone_shifted(&mut frames, &[0], 20.5), 0);
+        assert_eq!(frames.len(), 3);
+    }
+}